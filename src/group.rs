@@ -122,6 +122,13 @@ impl ops::Deref for GroupidBuf {
 
 impl private::Sealed for GroupidBuf {}
 
+#[cfg(unix)]
+impl crate::os::unix::GroupidBufExt for GroupidBuf {
+    fn from_raw_gid(gid: libc::gid_t) -> Self {
+        GroupidBuf(os_impl::GroupidBuf::from_raw_gid(gid))
+    }
+}
+
 #[cfg(windows)]
 impl crate::os::windows::GroupidBufExt for GroupidBuf {
     fn world() -> Result<Self, io::Error> {
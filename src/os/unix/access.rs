@@ -0,0 +1,170 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::os::unix::fs::{get_xattr, resolve_path, xattr_name, ACL_ACCESS_XATTR};
+use crate::os::unix::{get_group_list, Acl, GroupidExt, Passwd, UseridExt};
+use crate::Error;
+
+/// A kind of access to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Reading a file, or listing a directory.
+    Read,
+
+    /// Writing a file, or creating and removing entries of a directory.
+    Write,
+
+    /// Executing a file, or searching a directory.
+    Execute,
+}
+
+impl Access {
+    /// Returns the permission bit granting access to others, which is shifted
+    /// left by 3 for the group class and by 6 for the owner class.
+    fn other_bit(self) -> u32 {
+        match self {
+            Self::Read => 0o4,
+            Self::Write => 0o2,
+            Self::Execute => 0o1,
+        }
+    }
+}
+
+/// The credentials a user would act with, used to evaluate permissions.
+struct Credentials {
+    uid: libc::uid_t,
+    groups: Vec<libc::gid_t>,
+}
+
+impl Credentials {
+    fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Returns whether the access ACL or, without one, the permission bits of
+    /// the file at path grant access.
+    fn permits(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        access: Access,
+    ) -> Result<bool, io::Error> {
+        let mode = metadata.mode();
+
+        // Root has CAP_DAC_OVERRIDE, which bypasses every permission bit except
+        // the execute bits of a file that is not executable by anyone
+        if self.is_root() {
+            return Ok(access != Access::Execute || metadata.is_dir() || mode & 0o111 != 0);
+        }
+
+        if let Some(value) = get_xattr(path, xattr_name(ACL_ACCESS_XATTR))? {
+            let acl = Acl::from_xattr(&value)?;
+            return Ok(acl.permits_raw(
+                metadata.uid(),
+                metadata.gid(),
+                self.uid,
+                &self.groups,
+                access,
+            ));
+        }
+
+        let bit = access.other_bit();
+        Ok(if metadata.uid() == self.uid {
+            mode & (bit << 6) != 0
+        } else if self.groups.contains(&metadata.gid()) {
+            mode & (bit << 3) != 0
+        } else {
+            mode & bit != 0
+        })
+    }
+}
+
+/// Returns whether user could access the file at path, following symbolic links,
+/// without switching identity.
+///
+/// Permissions are evaluated as the kernel does for a process with the user id,
+/// primary group id and supplementary groups of user: every directory traversed
+/// while resolving path, including those traversed through symbolic links, must
+/// grant search permission, and the file must grant access. POSIX ACLs are
+/// evaluated for files that have one. A user id of 0 is treated as having
+/// `CAP_DAC_OVERRIDE`. Mount options, such as read-only file systems, are not
+/// taken into account.
+///
+/// Returns an error if path does not exist, unless user could not search one of
+/// its directories.
+///
+/// # libc functions used
+///
+/// - [`getgrouplist`](https://man7.org/linux/man-pages/man3/getgrouplist.3.html)
+/// - [`lstat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/lstat.html)
+/// - [`lgetxattr`](https://man7.org/linux/man-pages/man2/lgetxattr.2.html)
+pub fn can_access<P: AsRef<Path>>(pwd: &Passwd, path: P, access: Access) -> Result<bool, Error> {
+    let creds = Credentials {
+        uid: pwd.uid().as_raw_uid(),
+        groups: get_group_list(pwd.name(), pwd.gid().as_raw_gid())?,
+    };
+    let path = env::current_dir()?.join(path);
+
+    let resolved = resolve_path(&path, &mut |path, metadata| {
+        // Following a symbolic link needs no permission on the link itself
+        if metadata.file_type().is_symlink() {
+            Ok(true)
+        } else {
+            creds.permits(path, metadata, Access::Execute)
+        }
+    })?;
+
+    match resolved {
+        Some((path, metadata)) => Ok(creds.permits(&path, &metadata, access)?),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use crate::os::unix::test_util::TempDir;
+    use crate::os::unix::{get_pw_by_uid, set_access_acl};
+
+    #[test]
+    fn test_can_access() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = TempDir::new("access");
+        fs::create_dir_all(dir.join("private")).unwrap();
+        fs::write(dir.join("private/file"), b"").unwrap();
+        fs::set_permissions(dir.join("private/file"), fs::Permissions::from_mode(0o644)).unwrap();
+        fs::set_permissions(dir.join("private"), fs::Permissions::from_mode(0o700)).unwrap();
+        symlink("private/file", dir.join("link")).unwrap();
+
+        let root = get_pw_by_uid(0).unwrap();
+        let nobody = get_pw_by_uid(65534).unwrap();
+
+        assert!(can_access(&root, dir.join("link"), Access::Write).unwrap());
+        assert!(!can_access(&root, dir.join("link"), Access::Execute).unwrap());
+        assert!(!can_access(&nobody, dir.join("link"), Access::Read).unwrap());
+        assert!(!can_access(&nobody, dir.join("private/missing"), Access::Read).unwrap());
+        assert!(can_access(&root, dir.join("private/missing"), Access::Read).is_err());
+
+        fs::set_permissions(dir.join("private"), fs::Permissions::from_mode(0o711)).unwrap();
+
+        assert!(can_access(&nobody, dir.join("link"), Access::Read).unwrap());
+        assert!(!can_access(&nobody, dir.join("link"), Access::Write).unwrap());
+        assert!(can_access(&nobody, dir.join("private/../link"), Access::Read).unwrap());
+        assert!(!can_access(&nobody, dir.join("private"), Access::Read).unwrap());
+
+        let acl: Acl = "u::rw-,u:+65534:r--,g::---,m::r--,o::---".parse().unwrap();
+        if set_access_acl(dir.join("private/file"), &acl).is_ok() {
+            assert!(can_access(&nobody, dir.join("link"), Access::Read).unwrap());
+            assert!(!can_access(&nobody, dir.join("link"), Access::Write).unwrap());
+        }
+    }
+}
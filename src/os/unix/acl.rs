@@ -0,0 +1,637 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::os::unix::chown::{parse_group, parse_user};
+use crate::os::unix::fs::{
+    get_xattr, set_xattr, xattr_name, ACL_ACCESS_XATTR, ACL_DEFAULT_XATTR, ACL_GROUP, ACL_USER,
+    ACL_XATTR_VERSION,
+};
+use crate::os::unix::{Access, GroupidBufExt, GroupidExt, UseridBufExt, UseridExt};
+use crate::Error;
+
+// Tags of the ACL entries without a qualifier, from `<linux/posix_acl.h>`
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// The id stored in entries without a qualifier.
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+/// The read, write and execute permissions of an ACL entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AclPerms(u8);
+
+impl AclPerms {
+    /// Permission to read.
+    pub const READ: Self = Self(0o4);
+
+    /// Permission to write.
+    pub const WRITE: Self = Self(0o2);
+
+    /// Permission to execute or search.
+    pub const EXECUTE: Self = Self(0o1);
+
+    /// Creates a new `AclPerms` instance from permission bits, such as `0o5` for
+    /// `r-x`. Bits other than the lowest 3 are ignored.
+    #[inline]
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits & 0o7)
+    }
+
+    /// Returns the permission bits.
+    #[inline]
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns whether the permissions grant access.
+    pub fn allows(self, access: Access) -> bool {
+        let bit = match access {
+            Access::Read => Self::READ,
+            Access::Write => Self::WRITE,
+            Access::Execute => Self::EXECUTE,
+        };
+
+        self.0 & bit.0 != 0
+    }
+
+    /// Returns the permissions granted by both self and other.
+    #[inline]
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns the permissions granted by either self or other.
+    #[inline]
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for AclPerms {
+    /// Formats permissions like `getfacl(1)`, such as `r-x`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bit, c) in [(Self::READ, 'r'), (Self::WRITE, 'w'), (Self::EXECUTE, 'x')] {
+            let c = if self.0 & bit.0 != 0 { c } else { '-' };
+            write!(f, "{}", c)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for AclPerms {
+    type Err = Error;
+
+    /// Parses permissions in the format accepted by `setfacl(1)`, a combination
+    /// of `r`, `w`, `x` and `-` in any order, or an octal digit.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(bits @ 0..=7) = s.parse::<u8>() {
+            return Ok(Self(bits));
+        }
+
+        s.chars().try_fold(Self::default(), |perms, c| match c {
+            'r' => Ok(perms.union(Self::READ)),
+            'w' => Ok(perms.union(Self::WRITE)),
+            'x' => Ok(perms.union(Self::EXECUTE)),
+            '-' => Ok(perms),
+            _ => Err(invalid_input("invalid ACL permissions")),
+        })
+    }
+}
+
+/// The tag of an ACL entry, telling whom the entry applies to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AclTag {
+    /// The user owning the file.
+    UserObj,
+
+    /// The user with the given user id.
+    User(crate::UseridBuf),
+
+    /// The group owning the file.
+    GroupObj,
+
+    /// The group with the given group id.
+    Group(crate::GroupidBuf),
+
+    /// The maximum permissions granted to named users and to groups.
+    Mask,
+
+    /// Everyone else.
+    Other,
+}
+
+impl AclTag {
+    fn to_raw(&self) -> (u16, u32) {
+        match self {
+            Self::UserObj => (ACL_USER_OBJ, ACL_UNDEFINED_ID),
+            Self::User(uid) => (ACL_USER, uid.as_raw_uid()),
+            Self::GroupObj => (ACL_GROUP_OBJ, ACL_UNDEFINED_ID),
+            Self::Group(gid) => (ACL_GROUP, gid.as_raw_gid()),
+            Self::Mask => (ACL_MASK, ACL_UNDEFINED_ID),
+            Self::Other => (ACL_OTHER, ACL_UNDEFINED_ID),
+        }
+    }
+
+    fn from_raw(tag: u16, id: u32) -> Option<Self> {
+        match tag {
+            ACL_USER_OBJ => Some(Self::UserObj),
+            ACL_USER => Some(Self::User(crate::UseridBuf::from_raw_uid(id))),
+            ACL_GROUP_OBJ => Some(Self::GroupObj),
+            ACL_GROUP => Some(Self::Group(crate::GroupidBuf::from_raw_gid(id))),
+            ACL_MASK => Some(Self::Mask),
+            ACL_OTHER => Some(Self::Other),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the permissions of the entry are limited by the mask entry.
+    fn is_masked(&self) -> bool {
+        matches!(self, Self::User(_) | Self::GroupObj | Self::Group(_))
+    }
+}
+
+/// An entry of an ACL.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AclEntry {
+    tag: AclTag,
+    perms: AclPerms,
+}
+
+impl AclEntry {
+    /// Creates a new `AclEntry` instance.
+    pub fn new(tag: AclTag, perms: AclPerms) -> Self {
+        Self { tag, perms }
+    }
+
+    /// Returns whom the entry applies to.
+    #[inline]
+    pub fn tag(&self) -> &AclTag {
+        &self.tag
+    }
+
+    /// Returns the permissions of the entry, without applying the mask entry.
+    #[inline]
+    pub fn perms(&self) -> AclPerms {
+        self.perms
+    }
+}
+
+/// A POSIX access control list, as stored in the `system.posix_acl_access` and
+/// `system.posix_acl_default` extended attributes on Linux.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Creates a new `Acl` instance without any entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `Acl` instance equivalent to the permission bits of mode,
+    /// which is the access ACL of a file without extended ACL entries.
+    pub fn from_mode(mode: u32) -> Self {
+        let perms = |shift: u32| AclPerms::from_bits((mode >> shift) as u8);
+
+        Self {
+            entries: vec![
+                AclEntry::new(AclTag::UserObj, perms(6)),
+                AclEntry::new(AclTag::GroupObj, perms(3)),
+                AclEntry::new(AclTag::Other, perms(0)),
+            ],
+        }
+    }
+
+    /// Decodes the value of a `system.posix_acl_*` extended attribute.
+    pub fn from_xattr(value: &[u8]) -> Result<Self, io::Error> {
+        let invalid =
+            || io::Error::new(io::ErrorKind::InvalidData, "invalid ACL extended attribute");
+
+        if value.len() < 4 || (value.len() - 4) % 8 != 0 {
+            return Err(invalid());
+        }
+        let (header, entries) = value.split_at(4);
+        if u32::from_le_bytes(header.try_into().unwrap()) != ACL_XATTR_VERSION {
+            return Err(invalid());
+        }
+
+        let entries = entries
+            .chunks_exact(8)
+            .map(|entry| {
+                let tag = u16::from_le_bytes([entry[0], entry[1]]);
+                let perm = u16::from_le_bytes([entry[2], entry[3]]);
+                let id = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+
+                let tag = AclTag::from_raw(tag, id).ok_or_else(invalid)?;
+                Ok(AclEntry::new(tag, AclPerms::from_bits(perm as u8)))
+            })
+            .collect::<Result<_, io::Error>>()?;
+
+        Ok(Self { entries })
+    }
+
+    /// Encodes the ACL into the value of a `system.posix_acl_*` extended
+    /// attribute, with entries sorted in the order the kernel requires.
+    pub fn to_xattr(&self) -> Vec<u8> {
+        let mut entries: Vec<(u16, u32, u16)> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let (tag, id) = entry.tag.to_raw();
+                (tag, id, entry.perms.bits() as u16)
+            })
+            .collect();
+        entries.sort_unstable();
+
+        let mut value = ACL_XATTR_VERSION.to_le_bytes().to_vec();
+        for (tag, id, perm) in entries {
+            value.extend(tag.to_le_bytes());
+            value.extend(perm.to_le_bytes());
+            value.extend(id.to_le_bytes());
+        }
+
+        value
+    }
+
+    /// Returns the entries of the ACL.
+    #[inline]
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries
+    }
+
+    /// Returns the permissions of the entry with tag, if any.
+    pub fn get(&self, tag: &AclTag) -> Option<AclPerms> {
+        self.entries
+            .iter()
+            .find(|entry| entry.tag == *tag)
+            .map(AclEntry::perms)
+    }
+
+    /// Sets the permissions of the entry with tag, adding the entry if needed.
+    pub fn set(&mut self, tag: AclTag, perms: AclPerms) -> &mut Self {
+        match self.entries.iter_mut().find(|entry| entry.tag == tag) {
+            Some(entry) => entry.perms = perms,
+            None => self.entries.push(AclEntry::new(tag, perms)),
+        }
+        self
+    }
+
+    /// Removes the entry with tag, returning its permissions.
+    pub fn remove(&mut self, tag: &AclTag) -> Option<AclPerms> {
+        let index = self.entries.iter().position(|entry| entry.tag == *tag)?;
+
+        Some(self.entries.remove(index).perms)
+    }
+
+    /// Returns the permissions of the mask entry, if any.
+    pub fn mask(&self) -> Option<AclPerms> {
+        self.get(&AclTag::Mask)
+    }
+
+    /// Sets the mask entry to the union of the permissions of the named user,
+    /// owning group and named group entries, like `setfacl(1)` does.
+    pub fn recalculate_mask(&mut self) -> &mut Self {
+        let mask = self
+            .entries
+            .iter()
+            .filter(|entry| entry.tag.is_masked())
+            .fold(AclPerms::default(), |mask, entry| mask.union(entry.perms));
+
+        self.set(AclTag::Mask, mask)
+    }
+
+    /// Returns the permissions entry actually grants, after applying the mask entry.
+    pub fn effective_perms(&self, entry: &AclEntry) -> AclPerms {
+        self.apply_mask(&entry.tag, entry.perms)
+    }
+
+    fn apply_mask(&self, tag: &AclTag, perms: AclPerms) -> AclPerms {
+        match self.mask() {
+            Some(mask) if tag.is_masked() => perms.intersection(mask),
+            _ => perms,
+        }
+    }
+
+    /// Returns whether the ACL has entries beyond those equivalent to permission bits.
+    pub fn is_extended(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry.tag, AclTag::User(_) | AclTag::Group(_) | AclTag::Mask))
+    }
+
+    /// Returns whether the ACL has exactly one owning user, owning group and
+    /// other entry, at most one entry per named user or group, and a mask entry
+    /// if it has any named entry.
+    pub fn is_valid(&self) -> bool {
+        let count = |tag: &AclTag| {
+            self.entries
+                .iter()
+                .filter(|entry| entry.tag == *tag)
+                .count()
+        };
+
+        count(&AclTag::UserObj) == 1
+            && count(&AclTag::GroupObj) == 1
+            && count(&AclTag::Other) == 1
+            && count(&AclTag::Mask) <= 1
+            && self.entries.iter().all(|entry| count(&entry.tag) == 1)
+            && (!self.is_extended() || self.mask().is_some())
+    }
+
+    /// Returns whether the ACL grants access to a file owned by metadata to a
+    /// process with user id uid and groups, following the access check
+    /// algorithm of `acl(5)`.
+    ///
+    /// Privileges such as `CAP_DAC_OVERRIDE` are not taken into account.
+    pub fn permits(
+        &self,
+        metadata: &fs::Metadata,
+        uid: &crate::Userid,
+        groups: &[crate::GroupidBuf],
+        access: Access,
+    ) -> bool {
+        let groups: Vec<libc::gid_t> = groups.iter().map(|gid| gid.as_raw_gid()).collect();
+
+        self.permits_raw(
+            metadata.uid(),
+            metadata.gid(),
+            uid.as_raw_uid(),
+            &groups,
+            access,
+        )
+    }
+
+    pub(crate) fn permits_raw(
+        &self,
+        owner_uid: libc::uid_t,
+        owner_gid: libc::gid_t,
+        uid: libc::uid_t,
+        groups: &[libc::gid_t],
+        access: Access,
+    ) -> bool {
+        if uid == owner_uid {
+            return self
+                .get(&AclTag::UserObj)
+                .is_some_and(|perms| perms.allows(access));
+        }
+        let user = AclTag::User(crate::UseridBuf::from_raw_uid(uid));
+        if let Some(perms) = self.get(&user) {
+            return self.apply_mask(&user, perms).allows(access);
+        }
+
+        let mut matched_group = false;
+        for entry in &self.entries {
+            let matches = match entry.tag {
+                AclTag::GroupObj => groups.contains(&owner_gid),
+                AclTag::Group(ref gid) => groups.contains(&gid.as_raw_gid()),
+                _ => false,
+            };
+            if matches {
+                if self.effective_perms(entry).allows(access) {
+                    return true;
+                }
+                matched_group = true;
+            }
+        }
+        if matched_group {
+            return false;
+        }
+
+        self.get(&AclTag::Other)
+            .is_some_and(|perms| perms.allows(access))
+    }
+}
+
+impl fmt::Display for Acl {
+    /// Formats the ACL like `getfacl(1)`, one entry per line, with user and group
+    /// ids replaced by their names when they have a record.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<&AclEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.tag.to_raw());
+
+        for entry in entries {
+            match entry.tag {
+                AclTag::UserObj => write!(f, "user::")?,
+                AclTag::User(ref uid) => match uid.name() {
+                    Ok(name) => write!(f, "user:{}:", name.to_string_lossy())?,
+                    Err(_) => write!(f, "user:{}:", uid)?,
+                },
+                AclTag::GroupObj => write!(f, "group::")?,
+                AclTag::Group(ref gid) => match gid.name() {
+                    Ok(name) => write!(f, "group:{}:", name.to_string_lossy())?,
+                    Err(_) => write!(f, "group:{}:", gid)?,
+                },
+                AclTag::Mask => write!(f, "mask::")?,
+                AclTag::Other => write!(f, "other::")?,
+            }
+            write!(f, "{}", entry.perms)?;
+
+            let effective = self.effective_perms(entry);
+            if effective != entry.perms {
+                write!(f, "\t#effective:{}", effective)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Acl {
+    type Err = Error;
+
+    /// Parses an ACL in the text format accepted by `setfacl(1)`, with entries
+    /// such as `user:alice:r-x` or `g::rw-` separated by newlines or commas.
+    /// Comments starting with `#` are ignored.
+    ///
+    /// Names are searched in user and group database first, then parsed as
+    /// numeric ids.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut acl = Self::new();
+
+        for entry in s.lines().flat_map(|line| line.split(',')) {
+            let entry = entry.split('#').next().unwrap().trim();
+            if entry.is_empty() {
+                continue;
+            }
+
+            let mut fields = entry.splitn(3, ':');
+            let (tag, qualifier, perms) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(tag), Some(qualifier), Some(perms)) => (tag, qualifier, perms),
+                // mask and other entries may omit the empty qualifier
+                (Some(tag), Some(perms), None) => (tag, "", perms),
+                _ => return Err(invalid_input("invalid ACL entry")),
+            };
+
+            let tag = match (tag, qualifier) {
+                ("u" | "user", "") => AclTag::UserObj,
+                ("u" | "user", user) => {
+                    AclTag::User(crate::UseridBuf::from_raw_uid(parse_user(user)?.0))
+                }
+                ("g" | "group", "") => AclTag::GroupObj,
+                ("g" | "group", group) => {
+                    AclTag::Group(crate::GroupidBuf::from_raw_gid(parse_group(group)?))
+                }
+                ("m" | "mask", "") => AclTag::Mask,
+                ("o" | "other", "") => AclTag::Other,
+                _ => return Err(invalid_input("invalid ACL entry")),
+            };
+            acl.set(tag, perms.parse()?);
+        }
+
+        Ok(acl)
+    }
+}
+
+/// Returns the access ACL of the file at path, without following symbolic links.
+///
+/// The ACL of a file without extended ACL entries is built from its permission bits.
+///
+/// # libc functions used
+///
+/// - [`lgetxattr`](https://man7.org/linux/man-pages/man2/lgetxattr.2.html)
+pub fn get_access_acl<P: AsRef<Path>>(path: P) -> Result<Acl, io::Error> {
+    let path = path.as_ref();
+
+    match get_xattr(path, xattr_name(ACL_ACCESS_XATTR))? {
+        Some(value) => Acl::from_xattr(&value),
+        None => Ok(Acl::from_mode(fs::symlink_metadata(path)?.mode())),
+    }
+}
+
+/// Returns the default ACL of the directory at path, if any, without following
+/// symbolic links.
+///
+/// # libc functions used
+///
+/// - [`lgetxattr`](https://man7.org/linux/man-pages/man2/lgetxattr.2.html)
+pub fn get_default_acl<P: AsRef<Path>>(path: P) -> Result<Option<Acl>, io::Error> {
+    get_xattr(path.as_ref(), xattr_name(ACL_DEFAULT_XATTR))?
+        .map(|value| Acl::from_xattr(&value))
+        .transpose()
+}
+
+/// Sets the access ACL of the file at path, without following symbolic links,
+/// which also updates its permission bits.
+///
+/// # libc functions used
+///
+/// - [`lsetxattr`](https://man7.org/linux/man-pages/man2/lsetxattr.2.html)
+pub fn set_access_acl<P: AsRef<Path>>(path: P, acl: &Acl) -> Result<(), io::Error> {
+    set_acl_xattr(path.as_ref(), ACL_ACCESS_XATTR, acl)
+}
+
+/// Sets the default ACL of the directory at path, without following symbolic links.
+///
+/// # libc functions used
+///
+/// - [`lsetxattr`](https://man7.org/linux/man-pages/man2/lsetxattr.2.html)
+pub fn set_default_acl<P: AsRef<Path>>(path: P, acl: &Acl) -> Result<(), io::Error> {
+    set_acl_xattr(path.as_ref(), ACL_DEFAULT_XATTR, acl)
+}
+
+fn set_acl_xattr(path: &Path, name: &'static [u8], acl: &Acl) -> Result<(), io::Error> {
+    if !acl.is_valid() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid ACL"));
+    }
+
+    set_xattr(path, xattr_name(name), &acl.to_xattr())
+}
+
+fn invalid_input(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::os::unix::test_util::TempDir;
+
+    #[test]
+    fn test_xattr_roundtrip() {
+        let acl: Acl = "u::rw-,user:0:r--,g::r-x,group:+50:rwx,m::r--,o::---"
+            .parse()
+            .unwrap();
+        let value = acl.to_xattr();
+
+        assert_eq!(value.len(), 4 + 6 * 8);
+        assert_eq!(&value[..4], &ACL_XATTR_VERSION.to_le_bytes());
+        assert_eq!(Acl::from_xattr(&value).unwrap().to_xattr(), value);
+        assert!(Acl::from_xattr(&value[..10]).is_err());
+        assert!(acl.is_valid());
+        assert!(acl.is_extended());
+        assert!(!Acl::from_mode(0o644).is_extended());
+    }
+
+    #[test]
+    fn test_text_format() {
+        let acl: Acl =
+            "user::rwx\ngroup::r-x\ngroup:+4000123:rw- # comment\nmask::r--\nother::---\n"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            acl.to_string(),
+            "user::rwx\ngroup::r-x\t#effective:r--\ngroup:4000123:rw-\t#effective:r--\nmask::r--\nother::---\n"
+        );
+        assert_eq!(acl.to_string().parse::<Acl>().unwrap(), acl);
+        assert!("user::rwz".parse::<Acl>().is_err());
+        assert!("nobody:rwx".parse::<Acl>().is_err());
+        assert_eq!("5".parse::<AclPerms>().unwrap().to_string(), "r-x");
+    }
+
+    #[test]
+    fn test_permits() {
+        let mut acl: Acl = "u::rw-,u:+1000:rw-,g::r--,g:+50:rw-,o::---"
+            .parse()
+            .unwrap();
+        acl.recalculate_mask();
+        assert_eq!(acl.mask(), Some(AclPerms::from_bits(0o6)));
+
+        // Owner 0:0
+        assert!(acl.permits_raw(0, 0, 1000, &[], Access::Write));
+        assert!(acl.permits_raw(0, 0, 2000, &[50], Access::Write));
+        assert!(acl.permits_raw(0, 0, 2000, &[0, 50], Access::Write));
+        assert!(!acl.permits_raw(0, 0, 2000, &[0], Access::Write));
+        assert!(!acl.permits_raw(0, 0, 2000, &[], Access::Read));
+
+        acl.set(AclTag::Mask, AclPerms::READ);
+        assert!(!acl.permits_raw(0, 0, 1000, &[], Access::Write));
+        assert!(acl.permits_raw(0, 0, 1000, &[], Access::Read));
+        assert!(acl.permits_raw(1000, 0, 1000, &[], Access::Write));
+    }
+
+    #[test]
+    fn test_set_access_acl() {
+        let dir = TempDir::new("acl");
+        let path = dir.join("file");
+        fs::write(&path, b"").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        assert_eq!(get_access_acl(&path).unwrap(), Acl::from_mode(0o640));
+
+        let acl: Acl = "u::rw-,u:+4000123:r--,g::r--,m::r--,o::---"
+            .parse()
+            .unwrap();
+        match set_access_acl(&path, &acl) {
+            // The file system of the temporary directory may not support ACLs
+            Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => (),
+            result => {
+                result.unwrap();
+                assert_eq!(get_access_acl(&path).unwrap().to_xattr(), acl.to_xattr());
+            }
+        }
+        assert!(get_default_acl(dir.path()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+macro_rules! capabilities {
+    ($($(#[$doc:meta])* $variant:ident = $index:literal => $name:literal,)*) => {
+        /// A Linux capability.
+        ///
+        /// See [`capabilities(7)`](https://man7.org/linux/man-pages/man7/capabilities.7.html).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(u8)]
+        pub enum Capability {
+            $($(#[$doc])* $variant = $index,)*
+        }
+
+        impl Capability {
+            /// All capabilities known to this library, in bit order.
+            pub const ALL: &'static [Capability] = &[$(Capability::$variant,)*];
+
+            /// Returns the capability with the given bit index.
+            pub fn from_index(index: u8) -> Option<Self> {
+                match index {
+                    $($index => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the name of capability, such as `CAP_CHOWN`.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+capabilities! {
+    /// Make arbitrary changes to file uids and gids.
+    Chown = 0 => "CAP_CHOWN",
+    /// Bypass file read, write and execute permission checks.
+    DacOverride = 1 => "CAP_DAC_OVERRIDE",
+    /// Bypass file read and directory read and search permission checks.
+    DacReadSearch = 2 => "CAP_DAC_READ_SEARCH",
+    /// Bypass permission checks on operations requiring the file owner.
+    Fowner = 3 => "CAP_FOWNER",
+    /// Keep set-user-id and set-group-id bits when a file is modified.
+    Fsetid = 4 => "CAP_FSETID",
+    /// Bypass permission checks for sending signals.
+    Kill = 5 => "CAP_KILL",
+    /// Make arbitrary manipulations of process gids and supplementary gids.
+    Setgid = 6 => "CAP_SETGID",
+    /// Make arbitrary manipulations of process uids.
+    Setuid = 7 => "CAP_SETUID",
+    /// Modify the capability sets of the calling thread.
+    Setpcap = 8 => "CAP_SETPCAP",
+    /// Set the immutable and append-only file flags.
+    LinuxImmutable = 9 => "CAP_LINUX_IMMUTABLE",
+    /// Bind a socket to privileged ports.
+    NetBindService = 10 => "CAP_NET_BIND_SERVICE",
+    /// Make socket broadcasts and listen to multicasts.
+    NetBroadcast = 11 => "CAP_NET_BROADCAST",
+    /// Perform network administration operations.
+    NetAdmin = 12 => "CAP_NET_ADMIN",
+    /// Use raw and packet sockets.
+    NetRaw = 13 => "CAP_NET_RAW",
+    /// Lock memory.
+    IpcLock = 14 => "CAP_IPC_LOCK",
+    /// Bypass permission checks for System V IPC operations.
+    IpcOwner = 15 => "CAP_IPC_OWNER",
+    /// Load and unload kernel modules.
+    SysModule = 16 => "CAP_SYS_MODULE",
+    /// Perform I/O port operations.
+    SysRawio = 17 => "CAP_SYS_RAWIO",
+    /// Use `chroot(2)`.
+    SysChroot = 18 => "CAP_SYS_CHROOT",
+    /// Trace arbitrary processes.
+    SysPtrace = 19 => "CAP_SYS_PTRACE",
+    /// Use `acct(2)`.
+    SysPacct = 20 => "CAP_SYS_PACCT",
+    /// Perform a range of system administration operations.
+    SysAdmin = 21 => "CAP_SYS_ADMIN",
+    /// Use `reboot(2)` and `kexec_load(2)`.
+    SysBoot = 22 => "CAP_SYS_BOOT",
+    /// Raise process nice values and change scheduling of arbitrary processes.
+    SysNice = 23 => "CAP_SYS_NICE",
+    /// Override resource limits.
+    SysResource = 24 => "CAP_SYS_RESOURCE",
+    /// Set the system clock.
+    SysTime = 25 => "CAP_SYS_TIME",
+    /// Use `vhangup(2)`.
+    SysTtyConfig = 26 => "CAP_SYS_TTY_CONFIG",
+    /// Create special files using `mknod(2)`.
+    Mknod = 27 => "CAP_MKNOD",
+    /// Establish leases on arbitrary files.
+    Lease = 28 => "CAP_LEASE",
+    /// Write records to kernel auditing log.
+    AuditWrite = 29 => "CAP_AUDIT_WRITE",
+    /// Configure kernel auditing.
+    AuditControl = 30 => "CAP_AUDIT_CONTROL",
+    /// Set file capabilities.
+    Setfcap = 31 => "CAP_SETFCAP",
+    /// Override Mandatory Access Control.
+    MacOverride = 32 => "CAP_MAC_OVERRIDE",
+    /// Configure Mandatory Access Control.
+    MacAdmin = 33 => "CAP_MAC_ADMIN",
+    /// Perform privileged `syslog(2)` operations.
+    Syslog = 34 => "CAP_SYSLOG",
+    /// Trigger something that will wake up the system.
+    WakeAlarm = 35 => "CAP_WAKE_ALARM",
+    /// Block system suspend.
+    BlockSuspend = 36 => "CAP_BLOCK_SUSPEND",
+    /// Read the audit log through a multicast netlink socket.
+    AuditRead = 37 => "CAP_AUDIT_READ",
+    /// Use performance monitoring.
+    Perfmon = 38 => "CAP_PERFMON",
+    /// Use privileged BPF operations.
+    Bpf = 39 => "CAP_BPF",
+    /// Use checkpoint and restore operations.
+    CheckpointRestore = 40 => "CAP_CHECKPOINT_RESTORE",
+}
+
+impl Capability {
+    /// The capabilities that can be escalated to full root privileges, such as
+    /// by rewriting system files, changing to user id 0 or loading kernel code.
+    ///
+    /// See the "False Boundaries and Arbitrary Code Execution" analysis by
+    /// Brad Spengler for how each of them leads to root.
+    pub const ROOT_EQUIVALENT: &'static [Capability] = &[
+        Capability::Chown,
+        Capability::DacOverride,
+        Capability::DacReadSearch,
+        Capability::Fowner,
+        Capability::Setgid,
+        Capability::Setuid,
+        Capability::Mknod,
+        Capability::Setfcap,
+        Capability::SysAdmin,
+        Capability::SysModule,
+        Capability::SysPtrace,
+        Capability::SysRawio,
+        Capability::Bpf,
+    ];
+
+    /// Returns the bit index of capability.
+    #[inline]
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of Linux capabilities, as found in the `Cap*` masks of `/proc/<pid>/status`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CapabilitySet {
+    bits: u64,
+}
+
+impl CapabilitySet {
+    /// Creates a new `CapabilitySet` instance from a raw capability mask.
+    #[inline]
+    pub fn from_bits(bits: u64) -> Self {
+        Self { bits }
+    }
+
+    /// Returns the raw capability mask, including bits of capabilities unknown to this library.
+    #[inline]
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// Checks whether set contains no capabilities.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Checks whether set contains capability.
+    #[inline]
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.bits & (1 << capability.index()) != 0
+    }
+
+    /// Adds capability to set.
+    #[inline]
+    pub fn insert(&mut self, capability: Capability) {
+        self.bits |= 1 << capability.index();
+    }
+
+    /// Removes capability from set.
+    #[inline]
+    pub fn remove(&mut self, capability: Capability) {
+        self.bits &= !(1 << capability.index());
+    }
+
+    /// Returns an iterator over the known capabilities in set.
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        Capability::ALL
+            .iter()
+            .copied()
+            .filter(move |&capability| self.contains(capability))
+    }
+}
+
+impl FromIterator<Capability> for CapabilitySet {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        let mut set = Self::default();
+        for capability in iter {
+            set.insert(capability);
+        }
+
+        set
+    }
+}
+
+impl FromStr for CapabilitySet {
+    type Err = io::Error;
+
+    /// Parses a hexadecimal capability mask such as `000001ffffffffff`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s.trim(), 16)
+            .map(Self::from_bits)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid capability mask"))
+    }
+}
+
+impl fmt::Display for CapabilitySet {
+    /// Formats set as a comma separated list of capability names.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, capability) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(capability.name())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CapabilitySet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// The capability sets of a process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessCapabilities {
+    pub(crate) inheritable: CapabilitySet,
+    pub(crate) permitted: CapabilitySet,
+    pub(crate) effective: CapabilitySet,
+    pub(crate) bounding: CapabilitySet,
+    pub(crate) ambient: CapabilitySet,
+}
+
+impl ProcessCapabilities {
+    /// Returns the inheritable capability set (`CapInh`).
+    #[inline]
+    pub fn inheritable(&self) -> CapabilitySet {
+        self.inheritable
+    }
+
+    /// Returns the permitted capability set (`CapPrm`).
+    #[inline]
+    pub fn permitted(&self) -> CapabilitySet {
+        self.permitted
+    }
+
+    /// Returns the effective capability set (`CapEff`).
+    #[inline]
+    pub fn effective(&self) -> CapabilitySet {
+        self.effective
+    }
+
+    /// Returns the capability bounding set (`CapBnd`).
+    #[inline]
+    pub fn bounding(&self) -> CapabilitySet {
+        self.bounding
+    }
+
+    /// Returns the ambient capability set (`CapAmb`).
+    ///
+    /// Always empty on kernels before 4.3, which do not report it.
+    #[inline]
+    pub fn ambient(&self) -> CapabilitySet {
+        self.ambient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_set_parse_and_display() {
+        let set: CapabilitySet = "0000000000000003".parse().unwrap();
+
+        assert!(set.contains(Capability::Chown));
+        assert!(set.contains(Capability::DacOverride));
+        assert!(!set.contains(Capability::SysAdmin));
+        assert_eq!(set.to_string(), "CAP_CHOWN,CAP_DAC_OVERRIDE");
+        assert!("xyz".parse::<CapabilitySet>().is_err());
+    }
+
+    #[test]
+    fn test_capability_from_index() {
+        for (i, capability) in Capability::ALL.iter().enumerate() {
+            assert_eq!(Capability::from_index(i as u8), Some(*capability));
+        }
+        assert_eq!(Capability::from_index(63), None);
+    }
+}
@@ -0,0 +1,406 @@
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::os::unix::fs::walk_tree_at;
+use crate::os::unix::{
+    get_gr_by_name, get_pw_by_name, get_pw_by_uid, GroupidBufExt, GroupidExt, UseridBufExt,
+    UseridExt,
+};
+use crate::Error;
+
+/// Changes the owner of the file at path, following symbolic links.
+///
+/// An id of `None` is left unchanged.
+///
+/// # libc functions used
+///
+/// - [`chown`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/chown.html)
+pub fn set_owner<P: AsRef<Path>>(
+    path: P,
+    uid: Option<&crate::Userid>,
+    gid: Option<&crate::Groupid>,
+) -> Result<(), io::Error> {
+    let path = path_to_cstring(path.as_ref())?;
+    let (uid, gid) = raw_ids(uid, gid);
+
+    cvt(unsafe { libc::chown(path.as_ptr(), uid, gid) })
+}
+
+/// Changes the owner of the file at path, without following symbolic links.
+///
+/// An id of `None` is left unchanged.
+///
+/// # libc functions used
+///
+/// - [`lchown`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/lchown.html)
+pub fn set_owner_nofollow<P: AsRef<Path>>(
+    path: P,
+    uid: Option<&crate::Userid>,
+    gid: Option<&crate::Groupid>,
+) -> Result<(), io::Error> {
+    let path = path_to_cstring(path.as_ref())?;
+    let (uid, gid) = raw_ids(uid, gid);
+
+    cvt(unsafe { libc::lchown(path.as_ptr(), uid, gid) })
+}
+
+/// Changes the owner of the file referred to by fd.
+///
+/// An id of `None` is left unchanged.
+///
+/// # libc functions used
+///
+/// - [`fchown`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fchown.html)
+pub fn set_fd_owner<F: AsFd>(
+    fd: F,
+    uid: Option<&crate::Userid>,
+    gid: Option<&crate::Groupid>,
+) -> Result<(), io::Error> {
+    let (uid, gid) = raw_ids(uid, gid);
+
+    cvt(unsafe { libc::fchown(fd.as_fd().as_raw_fd(), uid, gid) })
+}
+
+/// Changes the owner of the file at path relative to the directory referred to by dirfd.
+///
+/// Symbolic links are followed only if `follow_symlinks` is `true`.
+/// An id of `None` is left unchanged.
+///
+/// # libc functions used
+///
+/// - [`fchownat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fchownat.html)
+pub fn set_owner_at<F: AsFd, P: AsRef<Path>>(
+    dirfd: F,
+    path: P,
+    uid: Option<&crate::Userid>,
+    gid: Option<&crate::Groupid>,
+    follow_symlinks: bool,
+) -> Result<(), io::Error> {
+    let path = path_to_cstring(path.as_ref())?;
+    let (uid, gid) = raw_ids(uid, gid);
+    let flags = if follow_symlinks {
+        0
+    } else {
+        libc::AT_SYMLINK_NOFOLLOW
+    };
+
+    cvt(unsafe { libc::fchownat(dirfd.as_fd().as_raw_fd(), path.as_ptr(), uid, gid, flags) })
+}
+
+/// An owner as given to `chown(1)`, such as `alice:staff`.
+///
+/// Either id may be absent, in which case it is left unchanged by [`RecursiveChown`]
+/// and matches any file when used as a filter.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct OwnerSpec {
+    uid: Option<crate::UseridBuf>,
+    gid: Option<crate::GroupidBuf>,
+}
+
+impl OwnerSpec {
+    /// Creates a new `OwnerSpec` instance.
+    pub fn new(uid: Option<crate::UseridBuf>, gid: Option<crate::GroupidBuf>) -> Self {
+        Self { uid, gid }
+    }
+
+    /// Returns the user id of owner.
+    #[inline]
+    pub fn uid(&self) -> Option<&crate::Userid> {
+        self.uid.as_deref()
+    }
+
+    /// Returns the group id of owner.
+    #[inline]
+    pub fn gid(&self) -> Option<&crate::Groupid> {
+        self.gid.as_deref()
+    }
+
+    /// Checks whether the file with the given metadata is owned by owner.
+    pub fn matches(&self, metadata: &fs::Metadata) -> bool {
+        self.matches_ids(metadata.uid(), metadata.gid())
+    }
+
+    fn matches_ids(&self, uid: libc::uid_t, gid: libc::gid_t) -> bool {
+        self.uid
+            .as_ref()
+            .map_or(true, |owner| owner.as_raw_uid() == uid)
+            && self
+                .gid
+                .as_ref()
+                .map_or(true, |owner| owner.as_raw_gid() == gid)
+    }
+}
+
+impl FromStr for OwnerSpec {
+    type Err = Error;
+
+    /// Parses an owner in one of the formats accepted by `chown(1)`:
+    ///
+    /// - `user` changes the user only.
+    /// - `user:group` changes both user and group.
+    /// - `user:` changes the user and the group to the login group of user.
+    /// - `:group` changes the group only.
+    ///
+    /// Names are searched in user and group database first, then parsed as numeric
+    /// ids. A `+` prefix forces a numeric id.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, group) = match s.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (s, None),
+        };
+
+        let mut spec = Self::default();
+        let mut login_gid = None;
+
+        if !user.is_empty() {
+            let (uid, gid) = parse_user(user)?;
+            spec.uid = Some(crate::UseridBuf::from_raw_uid(uid));
+            login_gid = gid;
+        }
+        match group {
+            Some("") if !user.is_empty() => {
+                // A numeric user without user database record has no login group
+                let gid = login_gid.ok_or(Error::NoRecord)?;
+                spec.gid = Some(crate::GroupidBuf::from_raw_gid(gid));
+            }
+            Some(group) if !group.is_empty() => {
+                spec.gid = Some(crate::GroupidBuf::from_raw_gid(parse_group(group)?));
+            }
+            _ => (),
+        }
+
+        if spec.uid.is_none() && spec.gid.is_none() {
+            Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "owner has neither user nor group",
+            )))
+        } else {
+            Ok(spec)
+        }
+    }
+}
+
+/// A builder for changing the owner of every file in a tree, like `chown -R`.
+///
+/// Directories reached through symbolic links are never descended into.
+#[derive(Debug)]
+pub struct RecursiveChown {
+    owner: OwnerSpec,
+    from: Option<OwnerSpec>,
+    no_dereference: bool,
+    preserve_root: bool,
+}
+
+impl RecursiveChown {
+    /// Creates a new `RecursiveChown` instance changing files to owner.
+    ///
+    /// By default, symbolic links themselves are changed instead of their targets,
+    /// and operating on `/` is refused.
+    pub fn new(owner: OwnerSpec) -> Self {
+        Self {
+            owner,
+            from: None,
+            no_dereference: true,
+            preserve_root: true,
+        }
+    }
+
+    /// Only changes files currently owned by from, like `chown --from`.
+    pub fn from(&mut self, from: OwnerSpec) -> &mut Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Sets whether symbolic links themselves are changed instead of their targets,
+    /// like `chown -h`.
+    pub fn no_dereference(&mut self, no_dereference: bool) -> &mut Self {
+        self.no_dereference = no_dereference;
+        self
+    }
+
+    /// Sets whether operating on `/` is refused, like `chown --preserve-root`.
+    pub fn preserve_root(&mut self, preserve_root: bool) -> &mut Self {
+        self.preserve_root = preserve_root;
+        self
+    }
+
+    /// Changes the owner of every file in the tree rooted at path, and returns
+    /// the number of files changed.
+    ///
+    /// Files are changed relative to their directory, so that swapping a
+    /// directory of the tree for a symbolic link while walking it cannot change
+    /// the owner of files outside of it.
+    ///
+    /// Stops at the first error, leaving the files visited before it changed
+    /// and the rest of the tree unchanged.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`openat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/openat.html)
+    /// - [`fstatat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fstatat.html)
+    /// - [`fchownat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fchownat.html)
+    pub fn run<P: AsRef<Path>>(&self, path: P) -> Result<usize, io::Error> {
+        let path = path.as_ref();
+
+        if self.preserve_root && fs::canonicalize(path)? == Path::new("/") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "refusing to operate recursively on /",
+            ));
+        }
+
+        let (uid, gid) = raw_ids(self.owner.uid(), self.owner.gid());
+        let mut changed = 0;
+        walk_tree_at(path, false, &mut |entry| {
+            let follow = entry.is_symlink() && !self.no_dereference;
+            let (file_uid, file_gid) = if follow {
+                entry.target_owner()?
+            } else {
+                (entry.uid(), entry.gid())
+            };
+
+            if self
+                .from
+                .as_ref()
+                .map_or(true, |from| from.matches_ids(file_uid, file_gid))
+            {
+                entry.set_owner(uid, gid, follow)?;
+                changed += 1;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(changed)
+    }
+}
+
+/// Returns the uid of user, and its login group if it has a user database record.
+pub(crate) fn parse_user(user: &str) -> Result<(libc::uid_t, Option<libc::gid_t>), Error> {
+    if let Some(uid) = user.strip_prefix('+') {
+        return parse_id(uid).map(|uid| (uid, None));
+    }
+
+    let pwd = match get_pw_by_name(OsStr::new(user)) {
+        Err(Error::NoRecord) => get_pw_by_uid(parse_id(user)?),
+        result => result,
+    };
+
+    match pwd {
+        Ok(pwd) => Ok((pwd.uid().as_raw_uid(), Some(pwd.gid().as_raw_gid()))),
+        Err(Error::NoRecord) => parse_id(user).map(|uid| (uid, None)),
+        Err(err) => Err(err),
+    }
+}
+
+pub(crate) fn parse_group(group: &str) -> Result<libc::gid_t, Error> {
+    if let Some(gid) = group.strip_prefix('+') {
+        return parse_id(gid);
+    }
+
+    match get_gr_by_name(OsStr::new(group)) {
+        Ok(grp) => Ok(grp.gid().as_raw_gid()),
+        Err(Error::NoRecord) => parse_id(group),
+        Err(err) => Err(err),
+    }
+}
+
+fn parse_id(id: &str) -> Result<u32, Error> {
+    match id.parse::<u32>() {
+        // -1 means the id is left unchanged by chown
+        Ok(id) if id != u32::MAX => Ok(id),
+        _ => Err(Error::NoRecord),
+    }
+}
+
+fn raw_ids(
+    uid: Option<&crate::Userid>,
+    gid: Option<&crate::Groupid>,
+) -> (libc::uid_t, libc::gid_t) {
+    (
+        uid.map_or(libc::uid_t::MAX, |uid| uid.as_raw_uid()),
+        gid.map_or(libc::gid_t::MAX, |gid| gid.as_raw_gid()),
+    )
+}
+
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString, io::Error> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a nul byte"))
+}
+
+fn cvt(return_code: libc::c_int) -> Result<(), io::Error> {
+    if return_code == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+
+    use crate::os::unix::test_util::TempDir;
+
+    #[test]
+    fn test_owner_spec_parse() {
+        let spec: OwnerSpec = "root:root".parse().unwrap();
+        assert_eq!(spec.uid().map(|uid| uid.as_raw_uid()), Some(0));
+        assert_eq!(spec.gid().map(|gid| gid.as_raw_gid()), Some(0));
+
+        let spec: OwnerSpec = "1234".parse().unwrap();
+        assert_eq!(spec.uid().map(|uid| uid.as_raw_uid()), Some(1234));
+        assert!(spec.gid().is_none());
+
+        let spec: OwnerSpec = ":+5678".parse().unwrap();
+        assert!(spec.uid().is_none());
+        assert_eq!(spec.gid().map(|gid| gid.as_raw_gid()), Some(5678));
+
+        let spec: OwnerSpec = "root:".parse().unwrap();
+        assert_eq!(spec.gid().map(|gid| gid.as_raw_gid()), Some(0));
+
+        assert!(matches!(
+            "user_utils-no-such-user".parse::<OwnerSpec>(),
+            Err(Error::NoRecord)
+        ));
+        assert!("1234:".parse::<OwnerSpec>().is_err());
+        assert!(":".parse::<OwnerSpec>().is_err());
+    }
+
+    #[test]
+    fn test_recursive_chown() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = TempDir::new("chown");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/file"), b"").unwrap();
+        symlink("/etc/passwd", dir.join("link")).unwrap();
+
+        let changed = RecursiveChown::new("+1234:+1234".parse().unwrap())
+            .from("root".parse().unwrap())
+            .run(dir.path())
+            .unwrap();
+
+        assert_eq!(changed, 4);
+        assert_eq!(fs::symlink_metadata(dir.join("link")).unwrap().uid(), 1234);
+        assert_eq!(fs::metadata(dir.join("link")).unwrap().uid(), 0);
+        assert_eq!(fs::metadata(dir.join("sub/file")).unwrap().gid(), 1234);
+    }
+
+    #[test]
+    fn test_recursive_chown_preserve_root() {
+        let result = RecursiveChown::new("+1234".parse().unwrap()).run("/");
+
+        assert!(matches!(result, Err(err) if err.kind() == io::ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,126 @@
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::os::unix::{get_group_list, GroupidExt, Passwd, UseridExt};
+use crate::private;
+use crate::Error;
+
+/// Extensions to [`Command`] for running a command as another user.
+pub trait UserCommandExt: private::Sealed {
+    /// Runs the command with the user id, primary group id and supplementary
+    /// groups of user, which usually requires an effective user id of 0.
+    ///
+    /// The supplementary groups are looked up in group database when this method
+    /// is called, and set in the child together with the group and user ids.
+    /// Do not combine this method with [`CommandExt::uid`] or [`CommandExt::gid`].
+    ///
+    /// # libc functions used
+    ///
+    /// - [`getgrouplist`](https://man7.org/linux/man-pages/man3/getgrouplist.3.html)
+    /// - [`setgroups`](https://man7.org/linux/man-pages/man2/setgroups.2.html)
+    /// - [`setgid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/setgid.html)
+    /// - [`setuid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/setuid.html)
+    fn as_user(&mut self, pwd: &Passwd) -> &mut Self;
+
+    /// Sets the `HOME`, `SHELL`, `USER` and `LOGNAME` environment variables of the command from user.
+    fn user_env(&mut self, pwd: &Passwd) -> &mut Self;
+
+    /// Sets the working directory of the command to the initial working directory of user.
+    fn user_dir(&mut self, pwd: &Passwd) -> &mut Self;
+
+    /// Sets `argv[0]` of the command to the file name of the program prefixed
+    /// with `-`, which tells shells to behave as a login shell.
+    fn login_arg0(&mut self) -> &mut Self;
+}
+
+impl private::Sealed for Command {}
+impl UserCommandExt for Command {
+    fn as_user(&mut self, pwd: &Passwd) -> &mut Self {
+        let uid = pwd.uid().as_raw_uid();
+        let gid = pwd.gid().as_raw_gid();
+        // Look up groups now, as group database cannot be safely searched after fork.
+        // Only the errno is kept, so that the error can be reported by every spawn
+        let groups = get_group_list(pwd.name(), gid).map_err(|err| match err {
+            Error::Io(err) => err.raw_os_error().unwrap_or(libc::EINVAL),
+            _ => libc::ENOENT,
+        });
+
+        unsafe {
+            self.pre_exec(move || {
+                let groups = groups
+                    .as_ref()
+                    .map_err(|&errno| io::Error::from_raw_os_error(errno))?;
+
+                if libc::setgroups(groups.len(), groups.as_ptr()) == -1
+                    || libc::setgid(gid) == -1
+                    || libc::setuid(uid) == -1
+                {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            })
+        }
+    }
+
+    fn user_env(&mut self, pwd: &Passwd) -> &mut Self {
+        self.env("HOME", pwd.dir())
+            .env("SHELL", pwd.shell())
+            .env("USER", pwd.name())
+            .env("LOGNAME", pwd.name())
+    }
+
+    fn user_dir(&mut self, pwd: &Passwd) -> &mut Self {
+        self.current_dir(pwd.dir())
+    }
+
+    fn login_arg0(&mut self) -> &mut Self {
+        let program = Path::new(self.get_program());
+        let file_name = program.file_name().unwrap_or(program.as_os_str());
+
+        let mut arg0 = OsString::from("-");
+        arg0.push(file_name);
+
+        self.arg0(arg0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::unix::get_pw_by_uid;
+
+    #[test]
+    fn test_as_user_nobody() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let pwd = get_pw_by_uid(65534).unwrap();
+        let output = Command::new("id")
+            .as_user(&pwd)
+            .user_env(&pwd)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(stdout.starts_with("uid=65534("));
+        assert!(!stdout.contains("groups=0("));
+    }
+
+    #[test]
+    fn test_login_arg0() {
+        let output = Command::new("/bin/sh")
+            .arg("-c")
+            .arg("echo $0")
+            .login_arg0()
+            .output()
+            .unwrap();
+
+        assert_eq!(output.stdout, b"-sh\n");
+    }
+}
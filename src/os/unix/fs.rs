@@ -0,0 +1,790 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::ptr;
+
+use crate::os::unix::chown::path_to_cstring;
+use crate::os::unix::{get_name_by_gid, get_name_by_uid, GroupidBufExt, UseridBufExt};
+use crate::private;
+use crate::Error;
+
+/// Unix-specific extensions to [`fs::Metadata`] returning the owner of a file
+/// as the id types of this library.
+///
+/// The ids are returned as owned [`UseridBuf`](crate::UseridBuf) and
+/// [`GroupidBuf`](crate::GroupidBuf) values, as [`fs::Metadata`] only hands out
+/// its ids by value, leaving nothing for a borrowed `&Userid` to point to.
+pub trait MetadataOwnerExt: private::Sealed {
+    /// Returns the user id owning the file.
+    fn owner(&self) -> crate::UseridBuf;
+
+    /// Returns the group id owning the file.
+    fn group(&self) -> crate::GroupidBuf;
+}
+
+impl private::Sealed for fs::Metadata {}
+impl MetadataOwnerExt for fs::Metadata {
+    fn owner(&self) -> crate::UseridBuf {
+        crate::UseridBuf::from_raw_uid(self.uid())
+    }
+
+    fn group(&self) -> crate::GroupidBuf {
+        crate::GroupidBuf::from_raw_gid(self.gid())
+    }
+}
+
+/// The user id and group id owning a file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileOwner {
+    uid: crate::UseridBuf,
+    gid: crate::GroupidBuf,
+}
+
+impl FileOwner {
+    fn from_raw(uid: libc::uid_t, gid: libc::gid_t) -> Self {
+        Self {
+            uid: crate::UseridBuf::from_raw_uid(uid),
+            gid: crate::GroupidBuf::from_raw_gid(gid),
+        }
+    }
+
+    /// Returns the user id owning the file.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Returns the group id owning the file.
+    #[inline]
+    pub fn gid(&self) -> &crate::Groupid {
+        &self.gid
+    }
+
+    /// Searches user database and returns the login name of the user owning the file.
+    pub fn user_name(&self) -> Result<OsString, Error> {
+        self.uid.name()
+    }
+
+    /// Searches group database and returns the name of the group owning the file.
+    pub fn group_name(&self) -> Result<OsString, Error> {
+        self.gid.name()
+    }
+}
+
+impl From<&fs::Metadata> for FileOwner {
+    fn from(metadata: &fs::Metadata) -> Self {
+        Self::from_raw(metadata.uid(), metadata.gid())
+    }
+}
+
+/// Returns the owner of the file at path, following symbolic links.
+///
+/// # libc functions used
+///
+/// - [`stat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/stat.html)
+pub fn path_owner<P: AsRef<Path>>(path: P) -> Result<FileOwner, io::Error> {
+    fs::metadata(path).map(|metadata| FileOwner::from(&metadata))
+}
+
+/// Returns the owner of the file at path, without following symbolic links.
+///
+/// # libc functions used
+///
+/// - [`lstat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/lstat.html)
+pub fn path_owner_nofollow<P: AsRef<Path>>(path: P) -> Result<FileOwner, io::Error> {
+    fs::symlink_metadata(path).map(|metadata| FileOwner::from(&metadata))
+}
+
+/// Returns the owner of the file referred to by fd.
+///
+/// # libc functions used
+///
+/// - [`fstat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fstat.html)
+pub fn fd_owner<F: AsFd>(fd: F) -> Result<FileOwner, io::Error> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+
+    if unsafe { libc::fstat(fd.as_fd().as_raw_fd(), &mut stat) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(FileOwner::from_raw(stat.st_uid, stat.st_gid))
+    }
+}
+
+/// A cache of the names of the users and groups owning files, so that walking
+/// a tree searches the user and group databases once per id.
+#[derive(Debug, Default)]
+pub(crate) struct OwnerNameCache {
+    users: HashMap<libc::uid_t, Option<OsString>>,
+    groups: HashMap<libc::gid_t, Option<OsString>>,
+}
+
+impl OwnerNameCache {
+    /// Returns the login name of uid, or `None` if uid has no record.
+    pub(crate) fn user_name(&mut self, uid: libc::uid_t) -> Result<Option<&OsStr>, Error> {
+        let name = match self.users.entry(uid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match get_name_by_uid(uid) {
+                Ok(name) => entry.insert(Some(name)),
+                Err(Error::NoRecord) => entry.insert(None),
+                Err(err) => return Err(err),
+            },
+        };
+
+        Ok(name.as_deref())
+    }
+
+    /// Returns the name of gid, or `None` if gid has no record.
+    pub(crate) fn group_name(&mut self, gid: libc::gid_t) -> Result<Option<&OsStr>, Error> {
+        let name = match self.groups.entry(gid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match get_name_by_gid(gid) {
+                Ok(name) => entry.insert(Some(name)),
+                Err(Error::NoRecord) => entry.insert(None),
+                Err(err) => return Err(err),
+            },
+        };
+
+        Ok(name.as_deref())
+    }
+}
+
+/// The extended attribute holding the access ACL of a file.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) const ACL_ACCESS_XATTR: &[u8] = b"system.posix_acl_access\0";
+
+/// The extended attribute holding the default ACL of a directory.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) const ACL_DEFAULT_XATTR: &[u8] = b"system.posix_acl_default\0";
+
+/// `POSIX_ACL_XATTR_VERSION` from `<linux/posix_acl_xattr.h>`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) const ACL_XATTR_VERSION: u32 = 2;
+
+/// Tag of an ACL entry holding a uid, from `<linux/posix_acl.h>`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) const ACL_USER: u16 = 0x02;
+
+/// Tag of an ACL entry holding a gid, from `<linux/posix_acl.h>`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) const ACL_GROUP: u16 = 0x08;
+
+/// Returns the extended attribute name as a `CStr`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn xattr_name(name: &'static [u8]) -> &'static CStr {
+    CStr::from_bytes_with_nul(name).unwrap()
+}
+
+/// Returns the value of the extended attribute name of the file at path,
+/// without following symbolic links.
+///
+/// Returns `None` if the file has no such attribute, or its file system does
+/// not support extended attributes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn get_xattr(path: &Path, name: &CStr) -> Result<Option<Vec<u8>>, io::Error> {
+    read_xattr(&path_to_cstring(path)?, name, libc::lgetxattr)
+}
+
+/// Sets the value of the extended attribute name of the file at path,
+/// without following symbolic links.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn set_xattr(path: &Path, name: &CStr, value: &[u8]) -> Result<(), io::Error> {
+    write_xattr(&path_to_cstring(path)?, name, value, libc::lsetxattr)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type GetXattrFn = unsafe extern "C" fn(
+    *const libc::c_char,
+    *const libc::c_char,
+    *mut libc::c_void,
+    libc::size_t,
+) -> libc::ssize_t;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type SetXattrFn = unsafe extern "C" fn(
+    *const libc::c_char,
+    *const libc::c_char,
+    *const libc::c_void,
+    libc::size_t,
+    libc::c_int,
+) -> libc::c_int;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_xattr(
+    path: &CStr,
+    name: &CStr,
+    getxattr: GetXattrFn,
+) -> Result<Option<Vec<u8>>, io::Error> {
+    loop {
+        let size = unsafe { getxattr(path.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+        if size == -1 {
+            return match io::Error::last_os_error() {
+                err if matches!(err.raw_os_error(), Some(libc::ENODATA | libc::ENOTSUP)) => {
+                    Ok(None)
+                }
+                err => Err(err),
+            };
+        }
+
+        let mut value: Vec<u8> = vec![0; size as usize];
+        let size = unsafe {
+            getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr() as *mut libc::c_void,
+                value.len(),
+            )
+        };
+        if size >= 0 {
+            value.truncate(size as usize);
+            return Ok(Some(value));
+        }
+
+        // Attribute grew between the 2 calls
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn write_xattr(
+    path: &CStr,
+    name: &CStr,
+    value: &[u8],
+    setxattr: SetXattrFn,
+) -> Result<(), io::Error> {
+    let return_code = unsafe {
+        setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+
+    if return_code == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// The maximum number of symbolic links followed while resolving a path,
+/// matching `MAXSYMLINKS` of Linux.
+const MAX_SYMLINKS: usize = 40;
+
+/// Resolves the absolute path component by component like the kernel does,
+/// following symbolic links, and returns the resolved path and metadata of the file.
+///
+/// f is called with every directory searched for a component, before the
+/// component is looked up, and with every symbolic link followed. Resolution
+/// stops and returns `None` as soon as f returns `false`.
+pub(crate) fn resolve_path(
+    path: &Path,
+    f: &mut dyn FnMut(&Path, &fs::Metadata) -> Result<bool, io::Error>,
+) -> Result<Option<(PathBuf, fs::Metadata)>, io::Error> {
+    let mut components = VecDeque::new();
+    push_components(&mut components, path);
+    let mut current = PathBuf::from("/");
+    let mut metadata = fs::metadata(&current)?;
+    let mut symlinks = 0;
+
+    while let Some(component) = components.pop_front() {
+        if !metadata.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        if !f(&current, &metadata)? {
+            return Ok(None);
+        }
+
+        if component == ".." {
+            current.pop();
+            metadata = fs::metadata(&current)?;
+            continue;
+        }
+
+        let next = current.join(&component);
+        let next_metadata = fs::symlink_metadata(&next)?;
+        if next_metadata.file_type().is_symlink() {
+            symlinks += 1;
+            if symlinks > MAX_SYMLINKS {
+                return Err(io::Error::from_raw_os_error(libc::ELOOP));
+            }
+            if !f(&next, &next_metadata)? {
+                return Ok(None);
+            }
+
+            let target = fs::read_link(&next)?;
+            if target.is_absolute() {
+                current = PathBuf::from("/");
+                metadata = fs::metadata(&current)?;
+            }
+            let mut target_components = VecDeque::new();
+            push_components(&mut target_components, &target);
+            target_components.append(&mut components);
+            components = target_components;
+        } else {
+            current = next;
+            metadata = next_metadata;
+        }
+    }
+
+    Ok(Some((current, metadata)))
+}
+
+fn push_components(components: &mut VecDeque<OsString>, path: &Path) {
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push_back(name.to_os_string()),
+            Component::ParentDir => components.push_back(OsString::from("..")),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+}
+
+/// A file found by [`walk_tree_at`], named relative to an open descriptor of
+/// its parent directory.
+///
+/// Operations on the entry never follow a symbolic link swapped in for any
+/// directory above it during the walk.
+pub(crate) struct TreeEntry<'a> {
+    dirfd: RawFd,
+    name: &'a CStr,
+    path: &'a Path,
+    stat: libc::stat,
+}
+
+impl TreeEntry<'_> {
+    /// Returns the path of the file, as joined from the root of the walk.
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        self.path
+    }
+
+    /// Returns the user id owning the file.
+    #[inline]
+    pub(crate) fn uid(&self) -> libc::uid_t {
+        self.stat.st_uid
+    }
+
+    /// Returns the group id owning the file.
+    #[inline]
+    pub(crate) fn gid(&self) -> libc::gid_t {
+        self.stat.st_gid
+    }
+
+    /// Returns the permission bits and file type of the file.
+    #[inline]
+    #[allow(clippy::unnecessary_cast)] // mode_t is u16 on some targets
+    pub(crate) fn mode(&self) -> u32 {
+        self.stat.st_mode as u32
+    }
+
+    /// Returns the device and inode numbers identifying the file.
+    #[inline]
+    #[allow(clippy::unnecessary_cast)] // dev_t and ino_t are u32 on some targets
+    pub(crate) fn dev_ino(&self) -> (u64, u64) {
+        (self.stat.st_dev as u64, self.stat.st_ino as u64)
+    }
+
+    /// Returns the number of hard links to the file.
+    #[inline]
+    #[allow(clippy::unnecessary_cast)] // nlink_t is u16 or u32 on some targets
+    pub(crate) fn nlink(&self) -> u64 {
+        self.stat.st_nlink as u64
+    }
+
+    /// Returns the size of the file in bytes.
+    #[inline]
+    #[allow(clippy::unnecessary_cast)] // off_t is i32 on some targets
+    pub(crate) fn size(&self) -> u64 {
+        self.stat.st_size as u64
+    }
+
+    /// Returns the number of 512-byte blocks allocated to the file.
+    #[inline]
+    #[allow(clippy::unnecessary_cast)] // blkcnt_t is i32 on some targets
+    pub(crate) fn blocks(&self) -> u64 {
+        self.stat.st_blocks as u64
+    }
+
+    /// Checks whether the file is a directory.
+    #[inline]
+    pub(crate) fn is_dir(&self) -> bool {
+        self.stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    /// Checks whether the file is a regular file.
+    #[inline]
+    pub(crate) fn is_file(&self) -> bool {
+        self.stat.st_mode & libc::S_IFMT == libc::S_IFREG
+    }
+
+    /// Checks whether the file is a symbolic link.
+    #[inline]
+    pub(crate) fn is_symlink(&self) -> bool {
+        self.stat.st_mode & libc::S_IFMT == libc::S_IFLNK
+    }
+
+    /// Returns the uid and gid owning the file, following a symbolic link.
+    pub(crate) fn target_owner(&self) -> Result<(libc::uid_t, libc::gid_t), io::Error> {
+        let stat = fstatat(self.dirfd, self.name, 0)?;
+
+        Ok((stat.st_uid, stat.st_gid))
+    }
+
+    /// Changes the owner of the file, or of the target of a symbolic link if
+    /// `follow_symlink` is `true`. An id of -1 is left unchanged.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`fchownat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fchownat.html)
+    pub(crate) fn set_owner(
+        &self,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+        follow_symlink: bool,
+    ) -> Result<(), io::Error> {
+        let flags = if follow_symlink {
+            0
+        } else {
+            libc::AT_SYMLINK_NOFOLLOW
+        };
+
+        if unsafe { libc::fchownat(self.dirfd, self.name.as_ptr(), uid, gid, flags) } == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Opens the file with flags, failing if it is a symbolic link or is no
+    /// longer the file found by the walk.
+    pub(crate) fn open(&self, flags: libc::c_int) -> Result<OwnedFd, io::Error> {
+        let fd = unsafe {
+            libc::openat(
+                self.dirfd,
+                self.name.as_ptr(),
+                flags | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is newly opened and owned by nothing else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if (stat.st_dev, stat.st_ino) != (self.stat.st_dev, self.stat.st_ino) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{} was replaced during the walk", self.path.display()),
+            ));
+        }
+
+        Ok(fd)
+    }
+
+    /// Restores the set-user-id and set-group-id bits of a regular file, as
+    /// found by the walk before its owner was changed, since changing the owner
+    /// of a file clears them. Returns whether they needed restoring.
+    pub(crate) fn restore_setid_bits(&self) -> Result<bool, io::Error> {
+        // 0o6000 is S_ISUID | S_ISGID
+        if !self.is_file() || self.mode() & 0o6000 == 0 {
+            return Ok(false);
+        }
+
+        let fd = self.open(libc::O_RDONLY | libc::O_NONBLOCK)?;
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mode = self.mode() & 0o7777;
+        if stat.st_mode as u32 & 0o7777 == mode {
+            Ok(false)
+        } else if unsafe { libc::fchmod(fd.as_raw_fd(), mode as libc::mode_t) } == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(true)
+        }
+    }
+
+    /// Returns the value of the extended attribute name of the file, or `None`
+    /// if it has no such attribute or its file system does not support them.
+    ///
+    /// The file is opened with `O_PATH` and read through `/proc/self/fd`, as
+    /// `fgetxattr(2)` needs a descriptor opened for reading, and opening a
+    /// device or FIFO for reading can have side effects. Fails for symbolic links.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn get_xattr(&self, name: &CStr) -> Result<Option<Vec<u8>>, io::Error> {
+        let fd = self.open(libc::O_PATH)?;
+
+        read_xattr(&proc_fd_path(&fd), name, libc::getxattr)
+    }
+
+    /// Sets the value of the extended attribute name of the file, opening it
+    /// like [`get_xattr`](Self::get_xattr). Fails for symbolic links.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn set_xattr(&self, name: &CStr, value: &[u8]) -> Result<(), io::Error> {
+        let fd = self.open(libc::O_PATH)?;
+
+        write_xattr(&proc_fd_path(&fd), name, value, libc::setxattr)
+    }
+}
+
+/// Returns the `/proc/self/fd` path of fd, which resolves to the file it
+/// refers to even if fd is an `O_PATH` descriptor.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn proc_fd_path(fd: &OwnedFd) -> CString {
+    CString::new(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap()
+}
+
+/// The maximum depth of directories [`walk_tree_at`] descends into, which bounds
+/// the number of directory descriptors it holds open at once.
+const MAX_WALK_DEPTH: usize = 256;
+
+/// A directory being walked by [`walk_tree_at`], with the names of its entries
+/// not visited yet.
+type OpenDir = (OwnedFd, std::vec::IntoIter<CString>);
+
+/// Walks the tree rooted at path in pre-order, without following symbolic links,
+/// calling f with every file.
+///
+/// Directories are opened relative to their parent with `O_NOFOLLOW` and files
+/// are named relative to their directory, so that swapping a directory of the
+/// tree for a symbolic link while walking cannot redirect the walk or the
+/// operations of f outside of the tree. Only path itself is resolved as a whole.
+///
+/// If `one_file_system` is `true`, directories on other file systems than
+/// path are passed to f, but not descended into.
+///
+/// Fails on reaching a directory nested [`MAX_WALK_DEPTH`] levels below path,
+/// as every level holds a directory descriptor open.
+pub(crate) fn walk_tree_at(
+    path: &Path,
+    one_file_system: bool,
+    f: &mut dyn FnMut(&TreeEntry) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+    let name = path_to_cstring(path)?;
+    let stat = fstatat(libc::AT_FDCWD, &name, libc::AT_SYMLINK_NOFOLLOW)?;
+    let dev = if one_file_system {
+        Some(stat.st_dev)
+    } else {
+        None
+    };
+    let mut path = path.to_path_buf();
+    let mut stack: Vec<OpenDir> = Vec::new();
+
+    if let Some(dir) = visit_entry_at(libc::AT_FDCWD, &name, &path, stat, dev, f)? {
+        stack.push(dir);
+    }
+
+    while let Some((dir, names)) = stack.last_mut() {
+        let dirfd = dir.as_raw_fd();
+        let name = match names.next() {
+            Some(name) => name,
+            None => {
+                stack.pop();
+                // The root of the walk has no component of its own to pop
+                if !stack.is_empty() {
+                    path.pop();
+                }
+                continue;
+            }
+        };
+        let stat = fstatat(dirfd, &name, libc::AT_SYMLINK_NOFOLLOW)?;
+        if stat.st_mode & libc::S_IFMT == libc::S_IFDIR && stack.len() == MAX_WALK_DEPTH {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "directory tree is nested too deeply",
+            ));
+        }
+
+        path.push(OsStr::from_bytes(name.to_bytes()));
+        match visit_entry_at(dirfd, &name, &path, stat, dev, f)? {
+            Some(dir) => stack.push(dir),
+            None => {
+                path.pop();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Calls f with the file name in the directory referred to by dirfd, and
+/// returns the file opened along with the names of its entries if the walk
+/// descends into it.
+fn visit_entry_at(
+    dirfd: RawFd,
+    name: &CStr,
+    path: &Path,
+    stat: libc::stat,
+    dev: Option<libc::dev_t>,
+    f: &mut dyn FnMut(&TreeEntry) -> Result<(), io::Error>,
+) -> Result<Option<OpenDir>, io::Error> {
+    let entry = TreeEntry {
+        dirfd,
+        name,
+        path,
+        stat,
+    };
+    f(&entry)?;
+
+    if !entry.is_dir() || dev.is_some_and(|dev| stat.st_dev != dev) {
+        return Ok(None);
+    }
+    let dir = entry.open(libc::O_RDONLY | libc::O_DIRECTORY)?;
+    let names = read_dir_names(&dir)?;
+
+    Ok(Some((dir, names.into_iter())))
+}
+
+/// Returns the names of the entries of the directory referred to by dir,
+/// except `.` and `..`.
+fn read_dir_names(dir: &OwnedFd) -> Result<Vec<CString>, io::Error> {
+    // closedir closes the fd given to fdopendir, so give it a duplicate
+    let fd = unsafe { libc::fcntl(dir.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let dirp = unsafe { libc::fdopendir(fd) };
+    if dirp.is_null() {
+        let err = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(err);
+    }
+
+    let mut names = Vec::new();
+    let result = loop {
+        // readdir only reports errors through errno
+        clear_errno();
+        let dirent = unsafe { libc::readdir(dirp) };
+        if dirent.is_null() {
+            let err = io::Error::last_os_error();
+            break match err.raw_os_error() {
+                Some(0) | None => Ok(()),
+                Some(_) => Err(err),
+            };
+        }
+
+        let name = unsafe { CStr::from_ptr((*dirent).d_name.as_ptr()) };
+        if name.to_bytes() != b"." && name.to_bytes() != b".." {
+            names.push(name.to_owned());
+        }
+    };
+    unsafe { libc::closedir(dirp) };
+
+    result.map(|()| names)
+}
+
+fn fstatat(dirfd: RawFd, name: &CStr, flags: libc::c_int) -> Result<libc::stat, io::Error> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+
+    if unsafe { libc::fstatat(dirfd, name.as_ptr(), &mut stat, flags) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(stat)
+    }
+}
+
+/// Sets errno of the calling thread to 0.
+fn clear_errno() {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        *libc::__errno_location() = 0;
+    }
+    #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+    unsafe {
+        *libc::__errno() = 0;
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    unsafe {
+        *libc::__error() = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+
+    use crate::os::unix::test_util::TempDir;
+    use crate::os::unix::{effective_uid, set_owner_nofollow, GroupidExt, UseridExt};
+
+    #[test]
+    fn test_owner() {
+        let dir = TempDir::new("owner");
+        fs::write(dir.join("file"), b"").unwrap();
+        let egid = unsafe { libc::getegid() };
+
+        let owner = path_owner(dir.join("file")).unwrap();
+        assert_eq!(*owner.uid(), *effective_uid());
+        assert_eq!(owner.gid().as_raw_gid(), egid);
+
+        let file = fs::File::open(dir.join("file")).unwrap();
+        assert_eq!(fd_owner(&file).unwrap(), owner);
+        assert_eq!(path_owner_nofollow(dir.join("file")).unwrap(), owner);
+
+        let metadata = file.metadata().unwrap();
+        assert_eq!(FileOwner::from(&metadata), owner);
+        assert_eq!(*metadata.owner(), *effective_uid());
+        assert_eq!(metadata.group().as_raw_gid(), egid);
+    }
+
+    #[test]
+    fn test_owner_nofollow() {
+        let dir = TempDir::new("owner-nofollow");
+        symlink("/", dir.join("link")).unwrap();
+
+        assert_eq!(path_owner(dir.join("link")).unwrap().uid().as_raw_uid(), 0);
+        assert_eq!(
+            *path_owner_nofollow(dir.join("link")).unwrap().uid(),
+            *effective_uid()
+        );
+
+        if effective_uid().as_raw_uid() == 0 {
+            let nobody = crate::Userid::from_raw_uid(&65534);
+            set_owner_nofollow(dir.join("link"), Some(nobody), None).unwrap();
+
+            assert_eq!(
+                *path_owner_nofollow(dir.join("link")).unwrap().uid(),
+                *nobody
+            );
+            assert_eq!(path_owner(dir.join("link")).unwrap().uid().as_raw_uid(), 0);
+        }
+    }
+
+    #[test]
+    fn test_walk_tree_at_depth() {
+        let dir = TempDir::new("walk-depth");
+        let deepest = dir.join("d/".repeat(MAX_WALK_DEPTH - 1));
+        fs::create_dir_all(&deepest).unwrap();
+
+        let mut dirs = 0;
+        walk_tree_at(dir.path(), false, &mut |entry| {
+            dirs += usize::from(entry.is_dir());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(dirs, MAX_WALK_DEPTH);
+
+        fs::create_dir(deepest.join("d")).unwrap();
+        assert!(walk_tree_at(dir.path(), false, &mut |_| Ok(())).is_err());
+    }
+}
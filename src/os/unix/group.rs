@@ -1,4 +1,4 @@
-use std::ffi::{c_char, CStr, OsStr, OsString};
+use std::ffi::{c_char, CStr, CString, OsStr, OsString};
 use std::fmt;
 use std::io;
 use std::mem;
@@ -25,6 +25,14 @@ pub trait GroupidExt: private::Sealed {
     fn lookup_group(&self) -> Result<Group, Error>;
 }
 
+/// Unix-specific extensions to [`GroupidBuf`](crate::GroupidBuf).
+pub trait GroupidBufExt: private::Sealed {
+    /// Creates a new `GroupidBuf` instance holding the given raw gid.
+    fn from_raw_gid(gid: libc::gid_t) -> Self
+    where
+        Self: Sized;
+}
+
 #[derive(PartialEq, Eq)]
 pub(crate) struct Groupid {
     raw_gid: libc::gid_t,
@@ -109,6 +117,13 @@ impl ops::Deref for GroupidBuf {
     }
 }
 
+impl private::Sealed for GroupidBuf {}
+impl GroupidBufExt for GroupidBuf {
+    fn from_raw_gid(gid: libc::gid_t) -> Self {
+        Self { raw_gid: gid }
+    }
+}
+
 /// Metadata information about a group.
 ///
 /// Newtype pattern around [`group`](https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/grp.h.html)
@@ -221,3 +236,96 @@ pub fn get_gr_by_gid(gid: libc::gid_t) -> Result<Group, Error> {
         }
     }
 }
+
+/// Searches group database and returns the group record of name.
+///
+/// # libc functions used
+///
+/// - [`getgrnam_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getgrnam_r.html)
+pub fn get_gr_by_name(name: &OsStr) -> Result<Group, Error> {
+    // A group name containing a nul byte cannot be in group database
+    let name = match CString::new(name.as_bytes()) {
+        Ok(name) => name,
+        Err(_) => return Err(Error::NoRecord),
+    };
+
+    let mut buflen = unsafe { libc::sysconf(libc::_SC_GETGR_R_SIZE_MAX) };
+    if buflen == -1 {
+        buflen = 1024;
+    }
+
+    let mut grp = Group {
+        raw_group: unsafe { mem::zeroed() },
+        buf: vec![0; buflen as usize],
+    };
+    let mut result: *mut libc::group = ptr::null_mut();
+
+    unsafe {
+        let return_code = libc::getgrnam_r(
+            name.as_ptr(),
+            &mut grp.raw_group,
+            grp.buf.as_mut_ptr(),
+            buflen as usize,
+            &mut result,
+        );
+
+        // On success, return_code is 0
+        if return_code == 0 {
+            // If group record is found for name, result is a pointer to grp
+            if result == &mut grp.raw_group {
+                Ok(grp)
+            } else {
+                Err(Error::NoRecord)
+            }
+        } else {
+            Err(Error::last_os_error())
+        }
+    }
+}
+
+/// Searches group database and returns the ids of all groups user with login
+/// name is a member of, including the given primary group id.
+///
+/// # libc functions used
+///
+/// - [`getgrouplist`](https://man7.org/linux/man-pages/man3/getgrouplist.3.html)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn get_group_list(name: &OsStr, gid: libc::gid_t) -> Result<Vec<libc::gid_t>, Error> {
+    // A login name containing a nul byte cannot be in user database
+    let name = match CString::new(name.as_bytes()) {
+        Ok(name) => name,
+        Err(_) => return Err(Error::NoRecord),
+    };
+    let mut ngroups_max = unsafe { libc::sysconf(libc::_SC_NGROUPS_MAX) };
+    if ngroups_max <= 0 {
+        ngroups_max = 65536;
+    }
+    let mut ngroups: libc::c_int = 32;
+
+    loop {
+        let mut groups: Vec<libc::gid_t> = vec![0; ngroups as usize];
+        let prev_ngroups = ngroups;
+        let return_code =
+            unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut ngroups) };
+
+        // On success, return_code is the number of groups
+        if return_code != -1 {
+            groups.truncate(ngroups as usize);
+            return Ok(groups);
+        }
+        // No user can be in more groups than the kernel allows, so a larger
+        // list means the group database is broken
+        if prev_ngroups as libc::c_long >= ngroups_max * 2 {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "getgrouplist keeps reporting more groups than NGROUPS_MAX",
+            )));
+        }
+        // If groups is too small, ngroups is set to the number of groups
+        // unless the libc implementation does not report it
+        if ngroups <= prev_ngroups {
+            ngroups = prev_ngroups * 2;
+        }
+        ngroups = ngroups.min((ngroups_max * 2) as libc::c_int);
+    }
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::os::unix::{GroupidBufExt, GroupidExt, UseridBufExt, UseridExt};
+
+/// The overflow id used by the kernel when `/proc/sys/kernel/overflow{u,g}id` is unreadable.
+const DEFAULT_OVERFLOW_ID: u32 = 65534;
+
+/// A single line of a user namespace id map.
+///
+/// Maps the ids `inside..inside + count` of a user namespace onto the ids
+/// `outside..outside + count` of its parent user namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdMapRange {
+    inside: u32,
+    outside: u32,
+    count: u32,
+}
+
+impl IdMapRange {
+    /// Creates a new `IdMapRange` instance.
+    #[inline]
+    pub fn new(inside: u32, outside: u32, count: u32) -> Self {
+        Self {
+            inside,
+            outside,
+            count,
+        }
+    }
+
+    /// Returns the first id of range inside the user namespace.
+    #[inline]
+    pub fn inside(&self) -> u32 {
+        self.inside
+    }
+
+    /// Returns the first id of range outside the user namespace.
+    #[inline]
+    pub fn outside(&self) -> u32 {
+        self.outside
+    }
+
+    /// Returns the number of ids in range.
+    #[inline]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    fn to_outside(self, id: u32) -> Option<u32> {
+        let offset = id.checked_sub(self.inside)?;
+
+        // A range running past u32::MAX maps nothing beyond it
+        (offset < self.count)
+            .then(|| self.outside.checked_add(offset))
+            .flatten()
+    }
+
+    fn to_inside(self, id: u32) -> Option<u32> {
+        let offset = id.checked_sub(self.outside)?;
+
+        (offset < self.count)
+            .then(|| self.inside.checked_add(offset))
+            .flatten()
+    }
+}
+
+/// A user namespace id map as found in `/proc/<pid>/uid_map` and `/proc/<pid>/gid_map`.
+///
+/// See [`user_namespaces(7)`](https://man7.org/linux/man-pages/man7/user_namespaces.7.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdMap {
+    ranges: Vec<IdMapRange>,
+    overflow_id: u32,
+}
+
+impl IdMap {
+    /// Creates a new `IdMap` instance from the given ranges.
+    ///
+    /// Unmapped ids are reported as the default overflow id `65534`.
+    pub fn from_ranges(ranges: Vec<IdMapRange>) -> Self {
+        Self {
+            ranges,
+            overflow_id: DEFAULT_OVERFLOW_ID,
+        }
+    }
+
+    /// Reads the uid map of the process with the given pid.
+    ///
+    /// Unmapped ids are reported as the value of `/proc/sys/kernel/overflowuid`.
+    pub fn read_uid_map(pid: libc::pid_t) -> Result<Self, io::Error> {
+        let map = Self::read(&format!("/proc/{}/uid_map", pid))?;
+
+        Ok(map.with_overflow_id(read_overflow_id("/proc/sys/kernel/overflowuid")))
+    }
+
+    /// Reads the gid map of the process with the given pid.
+    ///
+    /// Unmapped ids are reported as the value of `/proc/sys/kernel/overflowgid`.
+    pub fn read_gid_map(pid: libc::pid_t) -> Result<Self, io::Error> {
+        let map = Self::read(&format!("/proc/{}/gid_map", pid))?;
+
+        Ok(map.with_overflow_id(read_overflow_id("/proc/sys/kernel/overflowgid")))
+    }
+
+    fn read(path: &str) -> Result<Self, io::Error> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Returns the same id map, with unmapped ids reported as `overflow_id`.
+    #[inline]
+    pub fn with_overflow_id(mut self, overflow_id: u32) -> Self {
+        self.overflow_id = overflow_id;
+        self
+    }
+
+    /// Returns the ranges of id map.
+    #[inline]
+    pub fn ranges(&self) -> &[IdMapRange] {
+        &self.ranges
+    }
+
+    /// Returns the id reported for ids that are not mapped.
+    #[inline]
+    pub fn overflow_id(&self) -> u32 {
+        self.overflow_id
+    }
+
+    /// Translates an id inside the user namespace to the id outside of it.
+    ///
+    /// Returns `None` if id is not mapped.
+    pub fn to_outside(&self, id: u32) -> Option<u32> {
+        self.ranges.iter().find_map(|range| range.to_outside(id))
+    }
+
+    /// Translates an id outside the user namespace to the id inside of it.
+    ///
+    /// Returns `None` if id is not mapped.
+    pub fn to_inside(&self, id: u32) -> Option<u32> {
+        self.ranges.iter().find_map(|range| range.to_inside(id))
+    }
+
+    /// Translates an id inside the user namespace to the id outside of it,
+    /// reporting unmapped ids as the overflow id.
+    pub fn to_outside_or_overflow(&self, id: u32) -> u32 {
+        self.to_outside(id).unwrap_or(self.overflow_id)
+    }
+
+    /// Translates an id outside the user namespace to the id inside of it,
+    /// reporting unmapped ids as the overflow id.
+    pub fn to_inside_or_overflow(&self, id: u32) -> u32 {
+        self.to_inside(id).unwrap_or(self.overflow_id)
+    }
+
+    /// Translates a user id inside the user namespace to the user id outside of it.
+    pub fn outside_userid(&self, userid: &crate::Userid) -> crate::UseridBuf {
+        crate::UseridBuf::from_raw_uid(self.to_outside_or_overflow(userid.as_raw_uid()))
+    }
+
+    /// Translates a user id outside the user namespace to the user id inside of it.
+    pub fn inside_userid(&self, userid: &crate::Userid) -> crate::UseridBuf {
+        crate::UseridBuf::from_raw_uid(self.to_inside_or_overflow(userid.as_raw_uid()))
+    }
+
+    /// Translates a group id inside the user namespace to the group id outside of it.
+    pub fn outside_groupid(&self, groupid: &crate::Groupid) -> crate::GroupidBuf {
+        crate::GroupidBuf::from_raw_gid(self.to_outside_or_overflow(groupid.as_raw_gid()))
+    }
+
+    /// Translates a group id outside the user namespace to the group id inside of it.
+    pub fn inside_groupid(&self, groupid: &crate::Groupid) -> crate::GroupidBuf {
+        crate::GroupidBuf::from_raw_gid(self.to_inside_or_overflow(groupid.as_raw_gid()))
+    }
+
+    /// Checks whether id map is the identity map of the initial user namespace.
+    pub fn is_initial(&self) -> bool {
+        matches!(
+            self.ranges.as_slice(),
+            [range] if range.inside == 0 && range.outside == 0 && range.count == u32::MAX
+        )
+    }
+
+    /// Checks whether id 0 inside the user namespace is mapped to a non-zero id outside of it.
+    ///
+    /// This is the case for rootless containers, where root inside the
+    /// container is an unprivileged user on the host.
+    pub fn maps_root_to_unprivileged(&self) -> bool {
+        matches!(self.to_outside(0), Some(outside) if outside != 0)
+    }
+}
+
+impl FromStr for IdMap {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Vec::new();
+
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let mut fields = line.split_whitespace().map(u32::from_str);
+
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(inside)), Some(Ok(outside)), Some(Ok(count)), None) => {
+                    ranges.push(IdMapRange::new(inside, outside, count));
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid id map line: {:?}", line),
+                    ));
+                }
+            }
+        }
+
+        Ok(Self::from_ranges(ranges))
+    }
+}
+
+impl fmt::Display for IdMap {
+    /// Formats id map in the format accepted by `/proc/<pid>/uid_map`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for range in &self.ranges {
+            writeln!(f, "{} {} {}", range.inside, range.outside, range.count)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_overflow_id(path: &str) -> u32 {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(DEFAULT_OVERFLOW_ID)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idmap_parse_and_translate() {
+        let map: IdMap = "         0     100000      65536\n     65536       1000          1\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(map.ranges().len(), 2);
+        assert_eq!(map.to_outside(0), Some(100000));
+        assert_eq!(map.to_outside(65535), Some(165535));
+        assert_eq!(map.to_outside(65536), Some(1000));
+        assert_eq!(map.to_outside(65537), None);
+        assert_eq!(map.to_inside(1000), Some(65536));
+        assert_eq!(map.to_inside(99999), None);
+        assert_eq!(map.to_outside_or_overflow(70000), 65534);
+        assert!(map.maps_root_to_unprivileged());
+        assert!(!map.is_initial());
+    }
+
+    #[test]
+    fn test_idmap_parse_invalid() {
+        assert!("0 0".parse::<IdMap>().is_err());
+        assert!("0 0 1 1".parse::<IdMap>().is_err());
+        assert!("a 0 1".parse::<IdMap>().is_err());
+    }
+
+    #[test]
+    fn test_idmap_overflowing_range() {
+        let map: IdMap = "0 4294967295 10\n".parse().unwrap();
+
+        assert_eq!(map.to_outside(0), Some(u32::MAX));
+        assert_eq!(map.to_outside(1), None);
+        assert_eq!(map.to_inside(u32::MAX), Some(0));
+    }
+
+    #[test]
+    fn test_idmap_read_self() {
+        let map = IdMap::read_uid_map(unsafe { libc::getpid() }).unwrap();
+        let uid = unsafe { libc::getuid() };
+        let userid = crate::Userid::from_raw_uid(&uid);
+
+        assert_eq!(map.inside_userid(&map.outside_userid(userid)), *userid);
+    }
+
+    #[test]
+    fn test_idmap_display_roundtrip() {
+        let map = IdMap::from_ranges(vec![IdMapRange::new(0, 1000, 1)]);
+
+        assert_eq!(map.to_string(), "0 1000 1\n");
+        assert_eq!(map.to_string().parse::<IdMap>().unwrap(), map);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::os::unix::fs::{
+    walk_tree_at, xattr_name, TreeEntry, ACL_ACCESS_XATTR, ACL_DEFAULT_XATTR, ACL_GROUP, ACL_USER,
+    ACL_XATTR_VERSION,
+};
+use crate::os::unix::IdMap;
+
+/// The extended attributes holding the POSIX ACLs of a file.
+const ACL_XATTRS: [&[u8]; 2] = [ACL_ACCESS_XATTR, ACL_DEFAULT_XATTR];
+
+/// The name and shifted value of an ACL extended attribute.
+type ShiftedAcl = (&'static CStr, Vec<u8>);
+
+/// The direction ids are shifted in by a [`TreeShifter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftDirection {
+    /// Translates ids inside the user namespace to ids outside of it,
+    /// such as when preparing a rootfs for a rootless container.
+    ToOutside,
+
+    /// Translates ids outside the user namespace back to ids inside of it.
+    ToInside,
+}
+
+/// What a [`TreeShifter`] does with a file owned by an id outside the id map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmappedPolicy {
+    /// Leaves the file unchanged and reports it in [`ShiftReport::unmapped`].
+    Skip,
+
+    /// Stops shifting with an [`io::ErrorKind::InvalidData`] error.
+    Error,
+}
+
+/// Shifts the owner of every file in a tree, including the ids in POSIX ACLs,
+/// through a pair of user namespace id maps.
+///
+/// This is how the rootfs of a rootless container is moved into a subordinate
+/// id range when idmapped mounts are not available.
+#[derive(Debug, Clone)]
+pub struct TreeShifter {
+    uid_map: IdMap,
+    gid_map: IdMap,
+    direction: ShiftDirection,
+    unmapped: UnmappedPolicy,
+    one_file_system: bool,
+}
+
+impl TreeShifter {
+    /// Creates a new `TreeShifter` instance shifting ids to outside the maps,
+    /// skipping files owned by unmapped ids, and crossing file systems.
+    pub fn new(uid_map: IdMap, gid_map: IdMap) -> Self {
+        Self {
+            uid_map,
+            gid_map,
+            direction: ShiftDirection::ToOutside,
+            unmapped: UnmappedPolicy::Skip,
+            one_file_system: false,
+        }
+    }
+
+    /// Sets the direction ids are shifted in.
+    pub fn direction(&mut self, direction: ShiftDirection) -> &mut Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets what is done with files owned by unmapped ids.
+    pub fn unmapped(&mut self, unmapped: UnmappedPolicy) -> &mut Self {
+        self.unmapped = unmapped;
+        self
+    }
+
+    /// Sets whether directories on other file systems are not descended into.
+    pub fn one_file_system(&mut self, one_file_system: bool) -> &mut Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    fn shift_uid(&self, uid: u32) -> Option<u32> {
+        match self.direction {
+            ShiftDirection::ToOutside => self.uid_map.to_outside(uid),
+            ShiftDirection::ToInside => self.uid_map.to_inside(uid),
+        }
+    }
+
+    fn shift_gid(&self, gid: u32) -> Option<u32> {
+        match self.direction {
+            ShiftDirection::ToOutside => self.gid_map.to_outside(gid),
+            ShiftDirection::ToInside => self.gid_map.to_inside(gid),
+        }
+    }
+
+    /// Shifts the owner and ACLs of every file in the tree rooted at path,
+    /// without following symbolic links.
+    ///
+    /// Files are changed relative to their directory, so that swapping a
+    /// directory of the tree for a symbolic link while shifting it cannot
+    /// change files outside of it. Files with multiple hard links are shifted
+    /// once. Set-user-id and set-group-id bits are preserved.
+    pub fn shift<P: AsRef<Path>>(&self, path: P) -> Result<ShiftReport, io::Error> {
+        let mut report = ShiftReport::default();
+        let mut seen_inodes = HashSet::new();
+
+        walk_tree_at(path.as_ref(), self.one_file_system, &mut |entry| {
+            if !entry.is_dir() && entry.nlink() > 1 && !seen_inodes.insert(entry.dev_ino()) {
+                report.hardlinks_skipped += 1;
+                return Ok(());
+            }
+
+            let acls = if entry.is_symlink() {
+                Some(Vec::new())
+            } else {
+                self.shifted_acls(entry)?
+            };
+            let ids = (self.shift_uid(entry.uid()), self.shift_gid(entry.gid()));
+            let (uid, gid, acls) = match (ids, acls) {
+                ((Some(uid), Some(gid)), Some(acls)) => (uid, gid, acls),
+                _ => match self.unmapped {
+                    UnmappedPolicy::Skip => {
+                        report.unmapped.push(entry.path().to_path_buf());
+                        return Ok(());
+                    }
+                    UnmappedPolicy::Error => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{} is owned by an unmapped id", entry.path().display()),
+                        ));
+                    }
+                },
+            };
+
+            entry.set_owner(uid, gid, false)?;
+            entry.restore_setid_bits()?;
+            for (name, acl) in acls {
+                entry.set_xattr(name, &acl)?;
+                report.acls_shifted += 1;
+            }
+            report.files_shifted += 1;
+
+            Ok(())
+        })?;
+
+        Ok(report)
+    }
+
+    /// Returns the shifted ACLs of the file, or `None` if any of them holds an
+    /// unmapped id.
+    fn shifted_acls(&self, entry: &TreeEntry) -> Result<Option<Vec<ShiftedAcl>>, io::Error> {
+        let mut acls = Vec::new();
+
+        for name in ACL_XATTRS {
+            let name = xattr_name(name);
+            if let Some(mut acl) = entry.get_xattr(name)? {
+                let shifted = shift_acl_xattr(&mut acl, |tag, id| match tag {
+                    ACL_USER => self.shift_uid(id),
+                    ACL_GROUP => self.shift_gid(id),
+                    _ => Some(id),
+                })?;
+                if !shifted {
+                    return Ok(None);
+                }
+                acls.push((name, acl));
+            }
+        }
+
+        Ok(Some(acls))
+    }
+}
+
+/// A report of the changes made by [`TreeShifter::shift`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShiftReport {
+    files_shifted: u64,
+    hardlinks_skipped: u64,
+    acls_shifted: u64,
+    unmapped: Vec<PathBuf>,
+}
+
+impl ShiftReport {
+    /// Returns the number of files whose owner was shifted.
+    #[inline]
+    pub fn files_shifted(&self) -> u64 {
+        self.files_shifted
+    }
+
+    /// Returns the number of additional hard links to files already shifted.
+    #[inline]
+    pub fn hardlinks_skipped(&self) -> u64 {
+        self.hardlinks_skipped
+    }
+
+    /// Returns the number of access and default ACLs shifted.
+    #[inline]
+    pub fn acls_shifted(&self) -> u64 {
+        self.acls_shifted
+    }
+
+    /// Returns the files left unchanged because they hold an id outside the id map.
+    #[inline]
+    pub fn unmapped(&self) -> &[PathBuf] {
+        &self.unmapped
+    }
+}
+
+/// Rewrites the id of every entry in a `system.posix_acl_*` extended attribute
+/// value with f, which is given the tag and id of entry.
+///
+/// Returns `false` and leaves acl partially rewritten if f returns `None` for any entry.
+fn shift_acl_xattr(
+    acl: &mut [u8],
+    mut f: impl FnMut(u16, u32) -> Option<u32>,
+) -> Result<bool, io::Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid ACL extended attribute");
+
+    if acl.len() < 4 || (acl.len() - 4) % 8 != 0 {
+        return Err(invalid());
+    }
+    let (header, entries) = acl.split_at_mut(4);
+    if u32::from_le_bytes(header.try_into().unwrap()) != ACL_XATTR_VERSION {
+        return Err(invalid());
+    }
+
+    for entry in entries.chunks_exact_mut(8) {
+        let tag = u16::from_le_bytes([entry[0], entry[1]]);
+        let id = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+
+        match f(tag, id) {
+            Some(id) => entry[4..8].copy_from_slice(&id.to_le_bytes()),
+            None => return Ok(false),
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    use crate::os::unix::test_util::TempDir;
+    use crate::os::unix::{get_access_acl, set_access_acl, Acl, IdMapRange};
+
+    fn acl_entry(tag: u16, perm: u16, id: u32) -> Vec<u8> {
+        [
+            &tag.to_le_bytes()[..],
+            &perm.to_le_bytes(),
+            &id.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_shift_acl_xattr() {
+        let mut acl = [
+            &ACL_XATTR_VERSION.to_le_bytes()[..],
+            &acl_entry(0x01, 6, u32::MAX),
+            &acl_entry(ACL_USER, 4, 1000),
+            &acl_entry(ACL_GROUP, 4, 50),
+        ]
+        .concat();
+
+        let shifted = shift_acl_xattr(&mut acl, |tag, id| match tag {
+            ACL_USER | ACL_GROUP => Some(id + 100000),
+            _ => Some(id),
+        })
+        .unwrap();
+
+        assert!(shifted);
+        assert_eq!(&acl[4..12], &acl_entry(0x01, 6, u32::MAX)[..]);
+        assert_eq!(&acl[16..20], &101000u32.to_le_bytes());
+        assert_eq!(&acl[24..28], &100050u32.to_le_bytes());
+        assert!(shift_acl_xattr(&mut [2, 0, 0, 0, 1], |_, id| Some(id)).is_err());
+    }
+
+    #[test]
+    fn test_tree_shifter_roundtrip() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = TempDir::new("shift");
+        fs::write(dir.join("file"), b"").unwrap();
+        fs::hard_link(dir.join("file"), dir.join("link")).unwrap();
+        let acl: Acl = "u::rw-,u:+1000:r--,g::r--,m::r--,o::---".parse().unwrap();
+        // The file system of the temporary directory may not support ACLs
+        let has_acl = match set_access_acl(dir.join("file"), &acl) {
+            Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => false,
+            result => result.map(|()| true).unwrap(),
+        };
+
+        let map = IdMap::from_ranges(vec![IdMapRange::new(0, 100000, 65536)]);
+        let mut shifter = TreeShifter::new(map.clone(), map);
+
+        let report = shifter.shift(dir.path()).unwrap();
+        assert_eq!(report.files_shifted(), 2);
+        assert_eq!(report.hardlinks_skipped(), 1);
+        assert_eq!(fs::metadata(dir.join("file")).unwrap().uid(), 100000);
+        if has_acl {
+            let shifted: Acl = "u::rw-,u:+101000:r--,g::r--,m::r--,o::---".parse().unwrap();
+            assert_eq!(report.acls_shifted(), 1);
+            assert_eq!(get_access_acl(dir.join("link")).unwrap(), shifted);
+        }
+
+        let report = shifter
+            .direction(ShiftDirection::ToInside)
+            .shift(dir.path())
+            .unwrap();
+        assert_eq!(report.files_shifted(), 2);
+        assert_eq!(fs::metadata(dir.join("link")).unwrap().gid(), 0);
+        if has_acl {
+            assert_eq!(get_access_acl(dir.join("file")).unwrap(), acl);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+use crate::os::unix::{get_login, get_pw_by_name, login_uid, UseridBufExt, UseridExt};
+use crate::Error;
+
+/// The source [`invoking_user`] found the invoking user in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvokerSource {
+    /// The `SUDO_UID` or `SUDO_USER` environment variable set by `sudo(8)`.
+    Sudo,
+
+    /// The `DOAS_USER` environment variable set by `doas(1)`.
+    Doas,
+
+    /// The `PKEXEC_UID` environment variable set by `pkexec(1)`.
+    Pkexec,
+
+    /// The audit login user id in `/proc/self/loginuid`, set at login by
+    /// `pam_loginuid(8)`.
+    LoginUid,
+
+    /// The user logged in on the controlling terminal according to `getlogin(3)`.
+    ///
+    /// glibc reads `/proc/self/loginuid` first, which [`invoking_user`] has
+    /// already found unset before trying this source, so the name comes from
+    /// the `utmp(5)` record of the controlling terminal. musl takes it from the
+    /// `LOGNAME` environment variable instead.
+    Getlogin,
+}
+
+impl InvokerSource {
+    /// Returns how far the source can be trusted.
+    pub fn trust(self) -> InvokerTrust {
+        match self {
+            Self::Sudo | Self::Doas | Self::Pkexec => InvokerTrust::Environment,
+            Self::Getlogin if cfg!(target_env = "musl") => InvokerTrust::Environment,
+            Self::Getlogin => InvokerTrust::Session,
+            Self::LoginUid => InvokerTrust::Kernel,
+        }
+    }
+}
+
+/// How far the invoking user found by [`invoking_user`] can be trusted, from
+/// least to most trustworthy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InvokerTrust {
+    /// Taken from environment variables, which whoever starts the process can
+    /// set to anything.
+    Environment,
+
+    /// Taken from the `utmp(5)` record of the controlling terminal, which
+    /// only privileged processes can write, but which may be stale or missing.
+    Session,
+
+    /// Taken from the kernel, which only lets privileged processes set the
+    /// audit login user id, and keeps it across `su(1)` and `sudo(8)`.
+    Kernel,
+}
+
+/// The human user behind a possibly privileged process.
+#[derive(Debug)]
+pub struct InvokingUser {
+    uid: crate::UseridBuf,
+    source: InvokerSource,
+}
+
+impl InvokingUser {
+    /// Returns the user id of the invoking user.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Consumes the `InvokingUser`, returning the user id of the invoking user.
+    #[inline]
+    pub fn into_uid(self) -> crate::UseridBuf {
+        self.uid
+    }
+
+    /// Returns the source the invoking user was found in.
+    #[inline]
+    pub fn source(&self) -> InvokerSource {
+        self.source
+    }
+
+    /// Returns how far the invoking user can be trusted.
+    #[inline]
+    pub fn trust(&self) -> InvokerTrust {
+        self.source.trust()
+    }
+}
+
+/// Returns the human user behind the calling process, such as the user who ran
+/// it through `sudo(8)`, `doas(1)`, `pkexec(1)` or `su(1)`.
+///
+/// The sources are tried in order: `/proc/self/loginuid`, then `SUDO_UID` and
+/// `SUDO_USER`, `DOAS_USER` and `PKEXEC_UID`, then `getlogin(3)`. Names without
+/// a record in the user database are skipped.
+///
+/// The environment variables are only honored when the effective user id is 0,
+/// since anyone starting an unprivileged process can set them. Whoever starts a
+/// process as root can still forge them, so check [`InvokingUser::trust`]
+/// before making security decisions.
+///
+/// Returns [`Error::NoRecord`] if no source names a known user.
+///
+/// # libc functions used
+///
+/// - [`geteuid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/geteuid.html)
+/// - [`getlogin_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getlogin_r.html)
+/// - [`getpwnam_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getpwnam_r.html)
+pub fn invoking_user() -> Result<InvokingUser, Error> {
+    let euid = unsafe { libc::geteuid() };
+
+    find_invoking_user(
+        euid,
+        |key| env::var_os(key),
+        self_login_uid,
+        || get_login().ok(),
+    )
+}
+
+fn find_invoking_user(
+    euid: libc::uid_t,
+    var: impl Fn(&str) -> Option<OsString>,
+    login_uid: impl FnOnce() -> Option<libc::uid_t>,
+    login_name: impl FnOnce() -> Option<OsString>,
+) -> Result<InvokingUser, Error> {
+    let found = |uid, source| {
+        Ok(InvokingUser {
+            uid: crate::UseridBuf::from_raw_uid(uid),
+            source,
+        })
+    };
+    let var = |key| var(key).filter(|value| !value.is_empty() && euid == 0);
+
+    if let Some(uid) = login_uid() {
+        return found(uid, InvokerSource::LoginUid);
+    }
+    if let Some(uid) = var("SUDO_UID").and_then(|uid| parse_uid(&uid)) {
+        return found(uid, InvokerSource::Sudo);
+    }
+    if let Some(uid) = lookup_uid(var("SUDO_USER"))? {
+        return found(uid, InvokerSource::Sudo);
+    }
+    if let Some(uid) = lookup_uid(var("DOAS_USER"))? {
+        return found(uid, InvokerSource::Doas);
+    }
+    if let Some(uid) = var("PKEXEC_UID").and_then(|uid| parse_uid(&uid)) {
+        return found(uid, InvokerSource::Pkexec);
+    }
+    if let Some(uid) = lookup_uid(login_name())? {
+        return found(uid, InvokerSource::Getlogin);
+    }
+
+    Err(Error::NoRecord)
+}
+
+fn parse_uid(uid: &OsStr) -> Option<libc::uid_t> {
+    uid.to_str()?.parse().ok()
+}
+
+/// Returns the user id of name, or `None` if there is no name or it has no
+/// record in the user database.
+fn lookup_uid(name: Option<OsString>) -> Result<Option<libc::uid_t>, Error> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+
+    match get_pw_by_name(&name) {
+        Ok(pwd) => Ok(Some(pwd.uid().as_raw_uid())),
+        Err(Error::NoRecord) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the audit login user id of the calling process, unless it is unset.
+fn self_login_uid() -> Option<libc::uid_t> {
+    let pid = unsafe { libc::getpid() };
+
+    login_uid(pid).ok().flatten().map(|uid| uid.as_raw_uid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    #[test]
+    fn test_find_invoking_user() {
+        let user = find_invoking_user(
+            0,
+            vars(&[("SUDO_UID", "1000"), ("PKEXEC_UID", "1001")]),
+            || None,
+            || None,
+        )
+        .unwrap();
+        assert_eq!(user.uid().as_raw_uid(), 1000);
+        assert_eq!(user.source(), InvokerSource::Sudo);
+        assert_eq!(user.trust(), InvokerTrust::Environment);
+
+        let user = find_invoking_user(0, vars(&[("DOAS_USER", "root")]), || None, || None).unwrap();
+        assert_eq!(user.uid().as_raw_uid(), 0);
+        assert_eq!(user.source(), InvokerSource::Doas);
+
+        let user =
+            find_invoking_user(0, vars(&[("SUDO_UID", "1000")]), || Some(1002), || None).unwrap();
+        assert_eq!(user.uid().as_raw_uid(), 1002);
+        assert_eq!(user.trust(), InvokerTrust::Kernel);
+
+        let user = find_invoking_user(
+            0,
+            vars(&[("SUDO_USER", "no such user"), ("DOAS_USER", "no such user")]),
+            || None,
+            || Some(OsString::from("root")),
+        )
+        .unwrap();
+        assert_eq!(user.source(), InvokerSource::Getlogin);
+        assert!(user.trust() < InvokerTrust::Kernel);
+
+        assert!(matches!(
+            find_invoking_user(0, vars(&[("SUDO_UID", "")]), || None, || None),
+            Err(Error::NoRecord)
+        ));
+        assert!(matches!(
+            find_invoking_user(
+                0,
+                vars(&[]),
+                || None,
+                || Some(OsString::from("no such user"))
+            ),
+            Err(Error::NoRecord)
+        ));
+    }
+
+    #[test]
+    fn test_find_invoking_user_unprivileged() {
+        let forged = vars(&[
+            ("SUDO_UID", "0"),
+            ("SUDO_USER", "root"),
+            ("DOAS_USER", "root"),
+            ("PKEXEC_UID", "0"),
+        ]);
+
+        let user = find_invoking_user(1000, &forged, || None, || None);
+        assert!(matches!(user, Err(Error::NoRecord)));
+
+        let user = find_invoking_user(1000, &forged, || Some(1000), || None).unwrap();
+        assert_eq!(user.uid().as_raw_uid(), 1000);
+        assert_eq!(user.source(), InvokerSource::LoginUid);
+    }
+}
@@ -0,0 +1,413 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::os::unix::utmp::WIDE_UTMP;
+use crate::os::unix::{UseridBufExt, UseridExt};
+
+/// The path of the last login of every user.
+pub const LASTLOG_PATH: &str = "/var/log/lastlog";
+
+/// The path of the login failures of every user.
+pub const FAILLOG_PATH: &str = "/var/log/faillog";
+
+/// The size of a `struct lastlog` record.
+///
+/// Like the times of `struct utmp`, glibc keeps `ll_time` 32-bit on 32-bit
+/// targets and on the 64-bit targets defining `__WORDSIZE_TIME64_COMPAT32`,
+/// such as x86_64. It makes it a `time_t` on aarch64, s390x and loongarch64,
+/// as do musl and bionic on every 64-bit target.
+const LASTLOG_SIZE: usize = LL_TIME_SIZE + 32 + 256;
+
+/// The size of `ll_time`.
+const LL_TIME_SIZE: usize = if WIDE_UTMP { 8 } else { 4 };
+
+// Offsets of the fields of struct lastlog from <bits/utmp.h>
+const LL_TIME: usize = 0;
+const LL_LINE: (usize, usize) = (LL_TIME_SIZE, 32);
+const LL_HOST: (usize, usize) = (LL_TIME_SIZE + 32, 256);
+
+/// The size of a `struct faillog` record of shadow-utils, whose last fields
+/// are a `time_t` and a `long`.
+const FAILLOG_SIZE: usize = 16 + mem::size_of::<libc::time_t>() + mem::size_of::<libc::c_long>();
+
+// Offsets of the fields of struct faillog from <faillog.h>
+const FAIL_CNT: usize = 0;
+const FAIL_MAX: usize = 2;
+const FAIL_LINE: (usize, usize) = (4, 12);
+const FAIL_TIME: usize = 16;
+const FAIL_LOCKTIME: usize = 16 + mem::size_of::<libc::time_t>();
+
+/// The last login of a user, from `lastlog(8)`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LastlogEntry {
+    uid: crate::UseridBuf,
+    time: SystemTime,
+    line: OsString,
+    host: OsString,
+}
+
+impl LastlogEntry {
+    fn from_record(uid: libc::uid_t, record: &[u8]) -> Self {
+        let time = &record[LL_TIME..LL_TIME + LL_TIME_SIZE];
+        // 32-bit times are read as unsigned to reach past 2038
+        let time = if WIDE_UTMP {
+            i64::from_ne_bytes(time.try_into().unwrap()).max(0) as u64
+        } else {
+            u32::from_ne_bytes(time.try_into().unwrap()) as u64
+        };
+
+        Self {
+            uid: crate::UseridBuf::from_raw_uid(uid),
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(time),
+            line: string_at(record, LL_LINE),
+            host: string_at(record, LL_HOST),
+        }
+    }
+
+    /// Returns the user id of the user.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Returns the time of the last login.
+    #[inline]
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// Returns the terminal of the last login, without the `/dev/` prefix.
+    #[inline]
+    pub fn line(&self) -> &OsStr {
+        &self.line
+    }
+
+    /// Returns the remote host of the last login, if any.
+    #[inline]
+    pub fn host(&self) -> &OsStr {
+        &self.host
+    }
+}
+
+/// The login failures of a user, from `faillog(8)`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FaillogEntry {
+    uid: crate::UseridBuf,
+    failures: i16,
+    max_failures: i16,
+    line: OsString,
+    time: SystemTime,
+    lock_time: Duration,
+}
+
+impl FaillogEntry {
+    fn from_record(uid: libc::uid_t, record: &[u8]) -> Self {
+        let i16_at = |offset: usize| i16::from_ne_bytes([record[offset], record[offset + 1]]);
+        let time =
+            libc::time_t::from_ne_bytes(record[FAIL_TIME..FAIL_LOCKTIME].try_into().unwrap());
+        let lock_time =
+            libc::c_long::from_ne_bytes(record[FAIL_LOCKTIME..FAILLOG_SIZE].try_into().unwrap());
+
+        Self {
+            uid: crate::UseridBuf::from_raw_uid(uid),
+            failures: i16_at(FAIL_CNT),
+            max_failures: i16_at(FAIL_MAX),
+            line: string_at(record, FAIL_LINE),
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(time.max(0) as u64),
+            lock_time: Duration::from_secs(lock_time.max(0) as u64),
+        }
+    }
+
+    /// Returns the user id of the user.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Returns the number of login failures since the last successful login.
+    #[inline]
+    pub fn failures(&self) -> i16 {
+        self.failures
+    }
+
+    /// Returns the number of login failures after which the account is locked,
+    /// or 0 if there is no limit.
+    #[inline]
+    pub fn max_failures(&self) -> i16 {
+        self.max_failures
+    }
+
+    /// Returns the terminal of the last login failure.
+    #[inline]
+    pub fn line(&self) -> &OsStr {
+        &self.line
+    }
+
+    /// Returns the time of the last login failure.
+    #[inline]
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// Returns how long the account stays locked after exceeding the maximum
+    /// number of login failures.
+    #[inline]
+    pub fn lock_time(&self) -> Duration {
+        self.lock_time
+    }
+}
+
+/// A reader of a `lastlog` file, a sparse file holding the record of every
+/// user id at the offset of the user id times the size of a record.
+#[derive(Debug)]
+pub struct Lastlog {
+    records: SparseRecords,
+}
+
+impl Lastlog {
+    /// Opens the `lastlog` file at path, usually [`LASTLOG_PATH`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        SparseRecords::open(path.as_ref(), LASTLOG_SIZE).map(|records| Self { records })
+    }
+
+    /// Returns the last login of the user id, or `None` if it never logged in.
+    pub fn get(&self, uid: &crate::Userid) -> Result<Option<LastlogEntry>, io::Error> {
+        let uid = uid.as_raw_uid();
+
+        Ok(self
+            .records
+            .get(uid)?
+            .map(|record| LastlogEntry::from_record(uid, &record)))
+    }
+
+    /// Returns an iterator over the last login of every user id that logged in,
+    /// in ascending order of user id.
+    pub fn entries(&self) -> impl Iterator<Item = Result<LastlogEntry, io::Error>> + '_ {
+        self.records
+            .iter()
+            .map(|record| record.map(|(uid, record)| LastlogEntry::from_record(uid, &record)))
+    }
+}
+
+/// A reader of a `faillog` file, a sparse file holding the record of every
+/// user id at the offset of the user id times the size of a record.
+#[derive(Debug)]
+pub struct Faillog {
+    records: SparseRecords,
+}
+
+impl Faillog {
+    /// Opens the `faillog` file at path, usually [`FAILLOG_PATH`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        SparseRecords::open(path.as_ref(), FAILLOG_SIZE).map(|records| Self { records })
+    }
+
+    /// Returns the login failures of the user id, or `None` if it has no record.
+    pub fn get(&self, uid: &crate::Userid) -> Result<Option<FaillogEntry>, io::Error> {
+        let uid = uid.as_raw_uid();
+
+        Ok(self
+            .records
+            .get(uid)?
+            .map(|record| FaillogEntry::from_record(uid, &record)))
+    }
+
+    /// Returns an iterator over the login failures of every user id with a
+    /// record, in ascending order of user id.
+    pub fn entries(&self) -> impl Iterator<Item = Result<FaillogEntry, io::Error>> + '_ {
+        self.records
+            .iter()
+            .map(|record| record.map(|(uid, record)| FaillogEntry::from_record(uid, &record)))
+    }
+}
+
+/// A file of fixed-size records indexed by user id, where records of user ids
+/// without entry are all zeros, and usually holes.
+#[derive(Debug)]
+struct SparseRecords {
+    file: File,
+    size: usize,
+}
+
+impl SparseRecords {
+    fn open(path: &Path, size: usize) -> Result<Self, io::Error> {
+        File::open(path).map(|file| Self { file, size })
+    }
+
+    /// Returns the record of uid, or `None` if it is beyond the end of the file
+    /// or all zeros.
+    fn get(&self, uid: libc::uid_t) -> Result<Option<Vec<u8>>, io::Error> {
+        let mut record = vec![0; self.size];
+
+        match self
+            .file
+            .read_exact_at(&mut record, uid as u64 * self.size as u64)
+        {
+            Ok(()) if record.iter().any(|&b| b != 0) => Ok(Some(record)),
+            Ok(()) => Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns an iterator over the user ids and records that are not all zeros,
+    /// skipping the holes of the file.
+    fn iter(&self) -> impl Iterator<Item = Result<(libc::uid_t, Vec<u8>), io::Error>> + '_ {
+        let mut uid: Option<u64> = Some(0);
+
+        std::iter::from_fn(move || loop {
+            let next = uid?;
+            let data = match self.next_data(next * self.size as u64) {
+                Ok(Some(data)) => data,
+                Ok(None) => return None,
+                Err(err) => {
+                    uid = None;
+                    return Some(Err(err));
+                }
+            };
+
+            let current = (data / self.size as u64).max(next);
+            if current > libc::uid_t::MAX as u64 {
+                return None;
+            }
+            uid = Some(current + 1);
+            match self.get(current as libc::uid_t) {
+                Ok(Some(record)) => return Some(Ok((current as libc::uid_t, record))),
+                Ok(None) => (),
+                Err(err) => {
+                    uid = None;
+                    return Some(Err(err));
+                }
+            }
+        })
+    }
+
+    /// Returns the offset of the next data at or after offset, or `None` if
+    /// there is only a hole or the end of the file after it.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`lseek`](https://man7.org/linux/man-pages/man2/lseek.2.html) with `SEEK_DATA`
+    fn next_data(&self, offset: u64) -> Result<Option<u64>, io::Error> {
+        let data = unsafe {
+            libc::lseek(
+                self.file.as_raw_fd(),
+                offset as libc::off_t,
+                libc::SEEK_DATA,
+            )
+        };
+        if data != -1 {
+            return Ok(Some(data as u64));
+        }
+
+        match io::Error::last_os_error() {
+            err if err.raw_os_error() == Some(libc::ENXIO) => Ok(None),
+            // The file system does not support SEEK_DATA, read every record instead
+            err if err.raw_os_error() == Some(libc::EINVAL) => {
+                let len = self.file.metadata()?.len();
+                Ok((offset < len).then_some(offset))
+            }
+            err => Err(err),
+        }
+    }
+}
+
+fn string_at(record: &[u8], (offset, len): (usize, usize)) -> OsString {
+    let field = &record[offset..offset + len];
+    let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+
+    OsString::from_vec(field[..end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::unix::test_util::TempDir;
+
+    #[test]
+    fn test_lastlog() {
+        let dir = TempDir::new("lastlog");
+        let path = dir.join("lastlog");
+        let mut record = vec![0; LASTLOG_SIZE];
+        let time = if WIDE_UTMP {
+            1000i64.to_ne_bytes().to_vec()
+        } else {
+            1000u32.to_ne_bytes().to_vec()
+        };
+        record[LL_TIME..LL_TIME + LL_TIME_SIZE].copy_from_slice(&time);
+        record[LL_LINE.0..LL_LINE.0 + 5].copy_from_slice(b"pts/0");
+        record[LL_HOST.0..LL_HOST.0 + 9].copy_from_slice(b"192.0.2.1");
+
+        let file = File::create(&path).unwrap();
+        file.write_all_at(&record, 1000 * LASTLOG_SIZE as u64)
+            .unwrap();
+        file.write_all_at(&record, 100_000 * LASTLOG_SIZE as u64)
+            .unwrap();
+
+        let lastlog = Lastlog::open(&path).unwrap();
+        let entry = lastlog
+            .get(crate::Userid::from_raw_uid(&1000))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(*entry.uid(), *crate::Userid::from_raw_uid(&1000));
+        assert_eq!(
+            entry.time(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1000)
+        );
+        assert_eq!(entry.line(), "pts/0");
+        assert_eq!(entry.host(), "192.0.2.1");
+        assert!(lastlog
+            .get(crate::Userid::from_raw_uid(&0))
+            .unwrap()
+            .is_none());
+        assert!(lastlog
+            .get(crate::Userid::from_raw_uid(&200_000))
+            .unwrap()
+            .is_none());
+
+        let uids: Vec<libc::uid_t> = lastlog
+            .entries()
+            .map(|entry| entry.unwrap().uid().as_raw_uid())
+            .collect();
+        assert_eq!(uids, [1000, 100_000]);
+    }
+
+    #[test]
+    fn test_faillog() {
+        let dir = TempDir::new("faillog");
+        let path = dir.join("faillog");
+        let mut record = vec![0; FAILLOG_SIZE];
+        record[FAIL_CNT..FAIL_CNT + 2].copy_from_slice(&3i16.to_ne_bytes());
+        record[FAIL_MAX..FAIL_MAX + 2].copy_from_slice(&5i16.to_ne_bytes());
+        record[FAIL_LINE.0..FAIL_LINE.0 + 4].copy_from_slice(b"tty1");
+        record[FAIL_TIME..FAIL_LOCKTIME].copy_from_slice(&(2000 as libc::time_t).to_ne_bytes());
+        record[FAIL_LOCKTIME..].copy_from_slice(&(60 as libc::c_long).to_ne_bytes());
+
+        let file = File::create(&path).unwrap();
+        file.write_all_at(&record, 1001 * FAILLOG_SIZE as u64)
+            .unwrap();
+
+        let faillog = Faillog::open(&path).unwrap();
+        let entries: Vec<FaillogEntry> = faillog.entries().map(Result::unwrap).collect();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(*entries[0].uid(), *crate::Userid::from_raw_uid(&1001));
+        assert_eq!(entries[0].failures(), 3);
+        assert_eq!(entries[0].max_failures(), 5);
+        assert_eq!(entries[0].line(), "tty1");
+        assert_eq!(
+            entries[0].time(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(2000)
+        );
+        assert_eq!(entries[0].lock_time(), Duration::from_secs(60));
+    }
+}
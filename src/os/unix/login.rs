@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::os::unix::{Passwd, UseridExt};
+
+/// The `PATH` used by shadow-utils when `ENV_PATH` is not set in `login.defs`.
+const DEFAULT_ENV_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// The `PATH` used by shadow-utils when `ENV_SUPATH` is not set in `login.defs`.
+const DEFAULT_ENV_SUPATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// The mail spool directory used when neither `MAIL_DIR` nor `MAIL_FILE` is set in `login.defs`.
+const DEFAULT_MAIL_DIR: &str = "/var/mail";
+
+/// A builder for the environment variables `login(1)` and `su -l` give to a user.
+///
+/// The environment consists of `HOME`, `SHELL`, `USER`, `LOGNAME`, `MAIL` and
+/// `PATH`, followed by the preserved variables of the calling process and the
+/// variables set explicitly. `PATH` and `MAIL` are derived from `login.defs(5)`.
+#[derive(Debug, Clone)]
+pub struct LoginEnvironment {
+    name: OsString,
+    dir: OsString,
+    shell: OsString,
+    is_root: bool,
+    login_defs: PathBuf,
+    preserved: Vec<OsString>,
+    vars: Vec<(OsString, OsString)>,
+}
+
+impl LoginEnvironment {
+    /// Creates a new `LoginEnvironment` instance for user, preserving only `TERM`
+    /// as `su -l` does.
+    pub fn new(pwd: &Passwd) -> Self {
+        Self {
+            name: pwd.name().to_os_string(),
+            dir: pwd.dir().to_os_string(),
+            shell: pwd.shell().to_os_string(),
+            is_root: pwd.uid().as_raw_uid() == 0,
+            login_defs: PathBuf::from("/etc/login.defs"),
+            preserved: vec![OsString::from("TERM")],
+            vars: Vec::new(),
+        }
+    }
+
+    /// Reads `ENV_PATH`, `ENV_SUPATH`, `MAIL_DIR` and `MAIL_FILE` from the given
+    /// file instead of `/etc/login.defs`.
+    pub fn login_defs<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.login_defs = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Copies variable from the environment of the calling process, if it is set.
+    pub fn preserve<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.preserved.push(key.as_ref().to_os_string());
+        self
+    }
+
+    /// Sets variable, overriding any value derived from user or preserved.
+    pub fn var<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.vars
+            .push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    /// Returns the environment variables, sorted by name.
+    ///
+    /// A missing `login.defs` file is treated as empty.
+    pub fn build(&self) -> Result<BTreeMap<OsString, OsString>, io::Error> {
+        let login_defs = match fs::read(&self.login_defs) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let lookup = |key: &str| lookup_login_defs(&login_defs, key);
+        let mut vars = BTreeMap::new();
+
+        let shell = if self.shell.is_empty() {
+            OsString::from("/bin/sh")
+        } else {
+            self.shell.clone()
+        };
+        let path = if self.is_root {
+            lookup("ENV_SUPATH").unwrap_or_else(|| OsString::from(DEFAULT_ENV_SUPATH))
+        } else {
+            lookup("ENV_PATH").unwrap_or_else(|| OsString::from(DEFAULT_ENV_PATH))
+        };
+        let mail = match (lookup("MAIL_DIR"), lookup("MAIL_FILE")) {
+            (Some(mail_dir), _) => Path::new(&mail_dir).join(&self.name),
+            (None, Some(mail_file)) => Path::new(&self.dir).join(mail_file),
+            (None, None) => Path::new(DEFAULT_MAIL_DIR).join(&self.name),
+        };
+
+        vars.insert(OsString::from("HOME"), self.dir.clone());
+        vars.insert(OsString::from("SHELL"), shell);
+        vars.insert(OsString::from("USER"), self.name.clone());
+        vars.insert(OsString::from("LOGNAME"), self.name.clone());
+        vars.insert(OsString::from("MAIL"), mail.into_os_string());
+        vars.insert(OsString::from("PATH"), strip_path_prefix(path));
+
+        for key in &self.preserved {
+            if let Some(value) = env::var_os(key) {
+                vars.insert(key.clone(), value);
+            }
+        }
+        for (key, value) in &self.vars {
+            vars.insert(key.clone(), value.clone());
+        }
+
+        Ok(vars)
+    }
+
+    /// Clears the environment of command and sets it to the login environment.
+    pub fn apply_to(&self, command: &mut Command) -> Result<(), io::Error> {
+        command.env_clear().envs(self.build()?);
+
+        Ok(())
+    }
+}
+
+/// Returns the value of key in the contents of a `login.defs(5)` file.
+fn lookup_login_defs(contents: &[u8], key: &str) -> Option<OsString> {
+    // Search backwards, as the last definition of key wins
+    contents
+        .rsplit(|&b| b == b'\n')
+        .map(trim_ascii_whitespace)
+        .filter(|line| !line.starts_with(b"#"))
+        .filter_map(|line| {
+            let split = line.iter().position(|b| b.is_ascii_whitespace())?;
+            let (line_key, value) = line.split_at(split);
+
+            (line_key == key.as_bytes()).then(|| trim_ascii_whitespace(value))
+        })
+        .next()
+        .map(|value| {
+            let value = value
+                .strip_prefix(b"\"")
+                .and_then(|value| value.strip_suffix(b"\""))
+                .unwrap_or(value);
+
+            OsString::from_vec(value.to_vec())
+        })
+}
+
+fn trim_ascii_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+
+    &bytes[start..end]
+}
+
+/// Strips the optional `PATH=` prefix of `ENV_PATH` and `ENV_SUPATH` values.
+fn strip_path_prefix(path: OsString) -> OsString {
+    match path.as_bytes().strip_prefix(b"PATH=") {
+        Some(path) => OsString::from_vec(path.to_vec()),
+        None => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::unix::get_pw_by_uid;
+
+    #[test]
+    fn test_lookup_login_defs() {
+        let contents = b"# comment\nENV_PATH\tPATH=/usr/bin:/bin\n  MAIL_DIR  /var/spool/mail \n";
+
+        assert_eq!(
+            lookup_login_defs(contents, "ENV_PATH").map(strip_path_prefix),
+            Some(OsString::from("/usr/bin:/bin"))
+        );
+        assert_eq!(
+            lookup_login_defs(contents, "MAIL_DIR"),
+            Some(OsString::from("/var/spool/mail"))
+        );
+        assert_eq!(lookup_login_defs(contents, "ENV_SUPATH"), None);
+    }
+
+    #[test]
+    fn test_login_environment_build() {
+        let pwd = get_pw_by_uid(unsafe { libc::getuid() }).unwrap();
+        let vars = LoginEnvironment::new(&pwd)
+            .login_defs("/nonexistent/login.defs")
+            .var("LANG", "C.UTF-8")
+            .build()
+            .unwrap();
+
+        assert_eq!(vars[OsStr::new("HOME")], pwd.dir());
+        assert_eq!(vars[OsStr::new("USER")], pwd.name());
+        assert_eq!(vars[OsStr::new("LOGNAME")], pwd.name());
+        assert_eq!(vars[OsStr::new("LANG")], "C.UTF-8");
+        assert_eq!(
+            Path::new(&vars[OsStr::new("MAIL")]),
+            Path::new(DEFAULT_MAIL_DIR).join(pwd.name())
+        );
+    }
+}
@@ -1,7 +1,79 @@
 //! Unix-specific wrappers around user and group primitives.
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod access;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod acl;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod capability;
+mod chown;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod command;
+mod fs;
 mod group;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod idmap;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod idshift;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod invoker;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod lastlog;
+mod login;
+mod orphan;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod privilege;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod process;
+mod remap;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod run_as;
+mod secure;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod socket;
+#[cfg(test)]
+mod test_util;
+mod usage;
 mod user;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod userns;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod utmp;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use access::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use acl::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use capability::*;
+pub use chown::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use command::*;
+pub use fs::*;
 pub use group::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use idmap::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use idshift::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use invoker::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use lastlog::*;
+pub use login::*;
+pub use orphan::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use privilege::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use process::*;
+pub use remap::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use run_as::*;
+pub use secure::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use socket::*;
+pub use usage::*;
 pub use user::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use userns::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use utmp::*;
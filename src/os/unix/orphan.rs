@@ -0,0 +1,201 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::os::unix::fs::{walk_tree_at, OwnerNameCache, TreeEntry};
+use crate::os::unix::{GroupidBufExt, GroupidExt, UseridBufExt, UseridExt};
+use crate::Error;
+
+/// A scanner finding the files of a tree owned by a user id or group id
+/// without a record in the user or group database, such as the files left
+/// behind by `userdel(8)`.
+#[derive(Debug, Clone, Default)]
+pub struct OrphanScanner {
+    one_file_system: bool,
+}
+
+impl OrphanScanner {
+    /// Creates a new `OrphanScanner` instance crossing file systems.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether directories on other file systems are not descended into.
+    pub fn one_file_system(&mut self, one_file_system: bool) -> &mut Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Scans the tree rooted at path, without following symbolic links.
+    ///
+    /// Every distinct id is searched in the user or group database once.
+    pub fn scan<P: AsRef<Path>>(&self, path: P) -> Result<OrphanReport, Error> {
+        let mut report = OrphanReport::default();
+        let mut names = OwnerNameCache::default();
+        let mut lookup_error = None;
+
+        let result = walk_tree_at(path.as_ref(), self.one_file_system, &mut |entry| {
+            report.files_scanned += 1;
+
+            let (unknown_user, unknown_group) = match unknown_owner(&mut names, entry) {
+                Ok(unknown) => unknown,
+                Err(err) => {
+                    lookup_error = Some(err);
+                    return Err(io::Error::new(io::ErrorKind::Other, "lookup failed"));
+                }
+            };
+
+            if unknown_user || unknown_group {
+                report.orphans.push(OrphanedFile {
+                    path: entry.path().to_path_buf(),
+                    uid: crate::UseridBuf::from_raw_uid(entry.uid()),
+                    gid: crate::GroupidBuf::from_raw_gid(entry.gid()),
+                    unknown_user,
+                    unknown_group,
+                });
+            }
+
+            Ok(())
+        });
+
+        match lookup_error {
+            Some(err) => Err(err),
+            None => result.map(|()| report).map_err(Error::Io),
+        }
+    }
+}
+
+/// Returns whether the user id and group id owning a file have no record.
+fn unknown_owner(names: &mut OwnerNameCache, entry: &TreeEntry) -> Result<(bool, bool), Error> {
+    Ok((
+        names.user_name(entry.uid())?.is_none(),
+        names.group_name(entry.gid())?.is_none(),
+    ))
+}
+
+/// A file owned by a user id or group id without a record.
+#[derive(Debug)]
+pub struct OrphanedFile {
+    path: PathBuf,
+    uid: crate::UseridBuf,
+    gid: crate::GroupidBuf,
+    unknown_user: bool,
+    unknown_group: bool,
+}
+
+impl OrphanedFile {
+    /// Returns the path of the file.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the user id owning the file.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Returns the group id owning the file.
+    #[inline]
+    pub fn gid(&self) -> &crate::Groupid {
+        &self.gid
+    }
+
+    /// Returns whether the user id owning the file has no record.
+    #[inline]
+    pub fn has_unknown_user(&self) -> bool {
+        self.unknown_user
+    }
+
+    /// Returns whether the group id owning the file has no record.
+    #[inline]
+    pub fn has_unknown_group(&self) -> bool {
+        self.unknown_group
+    }
+}
+
+/// The result of [`OrphanScanner::scan`].
+#[derive(Debug, Default)]
+pub struct OrphanReport {
+    files_scanned: u64,
+    orphans: Vec<OrphanedFile>,
+}
+
+impl OrphanReport {
+    /// Returns the number of files found in tree.
+    #[inline]
+    pub fn files_scanned(&self) -> u64 {
+        self.files_scanned
+    }
+
+    /// Returns the orphaned files, in the order they were found.
+    #[inline]
+    pub fn orphans(&self) -> &[OrphanedFile] {
+        &self.orphans
+    }
+
+    /// Returns the distinct user ids without a record owning files, in ascending order.
+    pub fn unknown_uids(&self) -> Vec<crate::UseridBuf> {
+        self.orphans
+            .iter()
+            .filter(|orphan| orphan.unknown_user)
+            .map(|orphan| orphan.uid.as_raw_uid())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(crate::UseridBuf::from_raw_uid)
+            .collect()
+    }
+
+    /// Returns the distinct group ids without a record owning files, in ascending order.
+    pub fn unknown_gids(&self) -> Vec<crate::GroupidBuf> {
+        self.orphans
+            .iter()
+            .filter(|orphan| orphan.unknown_group)
+            .map(|orphan| orphan.gid.as_raw_gid())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(crate::GroupidBuf::from_raw_gid)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::os::unix::set_owner_nofollow;
+    use crate::os::unix::test_util::TempDir;
+
+    #[test]
+    fn test_scan_orphans() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = TempDir::new("orphan");
+        fs::write(dir.join("owned"), b"").unwrap();
+        fs::write(dir.join("orphan"), b"").unwrap();
+        set_owner_nofollow(
+            dir.join("orphan"),
+            Some(crate::Userid::from_raw_uid(&4000123)),
+            None,
+        )
+        .unwrap();
+
+        let report = OrphanScanner::new().scan(dir.path()).unwrap();
+
+        assert_eq!(report.files_scanned(), 3);
+        assert_eq!(report.orphans().len(), 1);
+        assert_eq!(report.orphans()[0].path(), dir.join("orphan"));
+        assert!(report.orphans()[0].has_unknown_user());
+        assert!(!report.orphans()[0].has_unknown_group());
+        assert_eq!(
+            report.unknown_uids(),
+            [crate::UseridBuf::from_raw_uid(4000123)]
+        );
+        assert!(report.unknown_gids().is_empty());
+    }
+}
@@ -0,0 +1,516 @@
+use std::io;
+use std::process;
+use std::ptr;
+
+use crate::os::unix::{get_group_list, Capability, CapabilitySet, GroupidExt, Passwd, UseridExt};
+use crate::Error;
+
+/// A guard that temporarily switches the effective user id, effective group id
+/// and supplementary groups of the calling process, restoring them on drop.
+///
+/// The ids are process-wide: every thread of the process acts with the assumed
+/// identity until the guard is dropped.
+///
+/// Call [`IdentityGuard::restore`] to handle a failure to restore the original
+/// identity.
+///
+/// # Aborts
+///
+/// Dropping the guard without calling [`IdentityGuard::restore`] aborts the
+/// process if the original identity cannot be restored, as continuing with
+/// the wrong credentials is a security bug.
+#[derive(Debug)]
+#[must_use = "the original identity is restored as soon as the guard is dropped"]
+pub struct IdentityGuard {
+    // Each field is only set once the matching id has been switched
+    euid: Option<libc::uid_t>,
+    egid: Option<libc::gid_t>,
+    groups: Option<Vec<libc::gid_t>>,
+}
+
+impl IdentityGuard {
+    /// Switches the effective user id, effective group id and supplementary groups
+    /// of the calling process, which usually requires an effective user id of 0.
+    ///
+    /// Supplementary groups and the effective group id are switched before the
+    /// effective user id, while the process still has the privilege to do so.
+    /// If any step fails, the ids already switched are restored.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`getgroups`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getgroups.html)
+    /// - [`setgroups`](https://man7.org/linux/man-pages/man2/setgroups.2.html)
+    /// - [`setegid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/setegid.html)
+    /// - [`seteuid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/seteuid.html)
+    pub fn assume(
+        userid: &crate::Userid,
+        groupid: &crate::Groupid,
+        groups: &[crate::GroupidBuf],
+    ) -> Result<Self, io::Error> {
+        let mut guard = Self {
+            euid: None,
+            egid: None,
+            groups: None,
+        };
+        let original_groups = get_groups()?;
+        let groups: Vec<libc::gid_t> = groups.iter().map(|gid| gid.as_raw_gid()).collect();
+
+        // If any of the calls below fails, dropping guard restores the ids already switched
+        set_groups(&groups)?;
+        guard.groups = Some(original_groups);
+
+        let egid = unsafe { libc::getegid() };
+        if unsafe { libc::setegid(groupid.as_raw_gid()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        guard.egid = Some(egid);
+
+        let euid = unsafe { libc::geteuid() };
+        if unsafe { libc::seteuid(userid.as_raw_uid()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        guard.euid = Some(euid);
+
+        Ok(guard)
+    }
+
+    /// Restores the original identity of the calling process, consuming the guard.
+    ///
+    /// On error the process may be left with a partially restored identity, and
+    /// should exit instead of carrying on.
+    pub fn restore(mut self) -> Result<(), Error> {
+        let result = self.restore_ids();
+
+        // Either way, there is nothing left for drop to restore
+        self.euid = None;
+        self.egid = None;
+        self.groups = None;
+
+        result.map_err(Error::Io)
+    }
+
+    fn restore_ids(&self) -> Result<(), io::Error> {
+        // Regain the original effective user id first, as it is needed to restore the rest
+        if let Some(euid) = self.euid {
+            if unsafe { libc::seteuid(euid) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some(egid) = self.egid {
+            if unsafe { libc::setegid(egid) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        match self.groups {
+            Some(ref groups) => set_groups(groups),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for IdentityGuard {
+    fn drop(&mut self) {
+        if self.restore_ids().is_err() {
+            process::abort();
+        }
+    }
+}
+
+/// `_LINUX_CAPABILITY_VERSION_3` from `<linux/capability.h>`.
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Options for permanently dropping the privileges of the calling process.
+#[derive(Debug, Clone, Default)]
+pub struct DropOptions {
+    keep_capabilities: CapabilitySet,
+    no_new_privs: bool,
+}
+
+impl DropOptions {
+    /// Creates a new `DropOptions` instance which keeps no capabilities and
+    /// leaves the `no_new_privs` flag untouched.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the capabilities kept in the permitted and effective sets after the drop.
+    pub fn keep_capabilities(&mut self, capabilities: CapabilitySet) -> &mut Self {
+        self.keep_capabilities = capabilities;
+        self
+    }
+
+    /// Sets whether the `no_new_privs` flag is set after the drop, so that
+    /// executed programs can never gain privileges through set-user-id bits
+    /// or file capabilities.
+    pub fn no_new_privs(&mut self, no_new_privs: bool) -> &mut Self {
+        self.no_new_privs = no_new_privs;
+        self
+    }
+
+    /// Permanently drops the privileges of the calling process to user.
+    ///
+    /// The supplementary groups are set from group database, followed by the real,
+    /// effective and saved group ids, and only then the real, effective and saved
+    /// user ids. Once done, the ids are read back and regaining user id 0 and
+    /// group id 0 is attempted, which must fail unless user is root.
+    ///
+    /// Must be called before any other thread is spawned. The capability sets
+    /// and the `no_new_privs` flag only change for the calling thread, and so do
+    /// the ids with a C library that does not apply them to every thread.
+    ///
+    /// On error the process may be left with partially dropped privileges and
+    /// should exit instead of carrying on.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`getgrouplist`](https://man7.org/linux/man-pages/man3/getgrouplist.3.html)
+    /// - [`prctl`](https://man7.org/linux/man-pages/man2/prctl.2.html)
+    /// - [`setgroups`](https://man7.org/linux/man-pages/man2/setgroups.2.html)
+    /// - [`setresgid`](https://man7.org/linux/man-pages/man2/setresgid.2.html)
+    /// - [`setresuid`](https://man7.org/linux/man-pages/man2/setresuid.2.html)
+    /// - [`capset`](https://man7.org/linux/man-pages/man2/capset.2.html)
+    pub fn drop_privileges_to(&self, pwd: &Passwd) -> Result<(), io::Error> {
+        let uid = pwd.uid().as_raw_uid();
+        let gid = pwd.gid().as_raw_gid();
+        let groups = get_group_list(pwd.name(), gid).map_err(|err| match err {
+            Error::Io(err) => err,
+            Error::NoRecord => io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "login name contains a nul byte",
+            ),
+        })?;
+
+        self.drop_to_ids(uid, gid, &groups).map_err(io::Error::from)
+    }
+
+    /// Permanently drops the privileges of the calling process to the given ids.
+    ///
+    /// Only makes system calls, without allocating or looking anything up, so
+    /// it may be called in the child of a multi-threaded process after `fork(2)`.
+    pub(crate) fn drop_to_ids(
+        &self,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+        groups: &[libc::gid_t],
+    ) -> Result<(), DropError> {
+        let keep_capabilities = !self.keep_capabilities.is_empty();
+
+        if keep_capabilities {
+            prctl(libc::PR_SET_KEEPCAPS, 1)?;
+        }
+
+        set_groups(groups)?;
+        if unsafe { libc::setresgid(gid, gid, gid) } == -1 {
+            return Err(DropError::last_os_error());
+        }
+        if unsafe { libc::setresuid(uid, uid, uid) } == -1 {
+            return Err(DropError::last_os_error());
+        }
+
+        if keep_capabilities {
+            set_capabilities(self.keep_capabilities)?;
+            prctl(libc::PR_SET_KEEPCAPS, 0)?;
+        }
+        if self.no_new_privs {
+            prctl(libc::PR_SET_NO_NEW_PRIVS, 1)?;
+        }
+
+        self.verify(uid, gid)
+    }
+
+    fn verify(&self, uid: libc::uid_t, gid: libc::gid_t) -> Result<(), DropError> {
+        let (mut ruid, mut euid, mut suid) = (0, 0, 0);
+        let (mut rgid, mut egid, mut sgid) = (0, 0, 0);
+
+        unsafe {
+            if libc::getresuid(&mut ruid, &mut euid, &mut suid) == -1
+                || libc::getresgid(&mut rgid, &mut egid, &mut sgid) == -1
+            {
+                return Err(DropError::last_os_error());
+            }
+        }
+        if [ruid, euid, suid] != [uid; 3] || [rgid, egid, sgid] != [gid; 3] {
+            return Err(DropError::IdsMismatch);
+        }
+
+        let can_setuid = self.keep_capabilities.contains(Capability::Setuid);
+        if uid != 0 && !can_setuid && unsafe { libc::setuid(0) } != -1 {
+            return Err(DropError::UidRegained);
+        }
+
+        let can_setgid = self.keep_capabilities.contains(Capability::Setgid);
+        if uid != 0 && gid != 0 && !can_setgid && unsafe { libc::setresgid(0, 0, 0) } != -1 {
+            return Err(DropError::GidRegained);
+        }
+
+        Ok(())
+    }
+}
+
+/// A failure to drop privileges, kept free of allocations so that it can be
+/// reported from the child of a multi-threaded process after `fork(2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DropError {
+    /// A system call failed with the given error number.
+    Os(i32),
+
+    /// The ids read back do not match the user.
+    IdsMismatch,
+
+    /// User id 0 could be regained.
+    UidRegained,
+
+    /// Group id 0 could be regained.
+    GidRegained,
+}
+
+impl DropError {
+    fn last_os_error() -> Self {
+        Self::from(io::Error::last_os_error())
+    }
+
+    /// Returns the error as a single integer, which is the error number of
+    /// system call failures and negative otherwise.
+    pub(crate) fn to_raw(self) -> i32 {
+        match self {
+            Self::Os(code) => code,
+            Self::IdsMismatch => -1,
+            Self::UidRegained => -2,
+            Self::GidRegained => -3,
+        }
+    }
+
+    /// Returns the error encoded by [`DropError::to_raw`].
+    pub(crate) fn from_raw(raw: i32) -> Self {
+        match raw {
+            -1 => Self::IdsMismatch,
+            -2 => Self::UidRegained,
+            -3 => Self::GidRegained,
+            code => Self::Os(code),
+        }
+    }
+}
+
+impl From<io::Error> for DropError {
+    fn from(err: io::Error) -> Self {
+        Self::Os(err.raw_os_error().unwrap_or(libc::EIO))
+    }
+}
+
+impl From<DropError> for io::Error {
+    fn from(err: DropError) -> Self {
+        let msg = match err {
+            DropError::Os(code) => return io::Error::from_raw_os_error(code),
+            DropError::IdsMismatch => "ids of process do not match user after dropping privileges",
+            DropError::UidRegained => "user id 0 could be regained after dropping privileges",
+            DropError::GidRegained => "group id 0 could be regained after dropping privileges",
+        };
+
+        io::Error::new(io::ErrorKind::PermissionDenied, msg)
+    }
+}
+
+/// Permanently drops the privileges of the calling process to user, keeping no capabilities.
+///
+/// This is a shorthand for `DropOptions::new().drop_privileges_to(pwd)`, and
+/// must likewise be called before any other thread is spawned.
+/// See [`DropOptions::drop_privileges_to`] for details.
+pub fn drop_privileges_to(pwd: &Passwd) -> Result<(), io::Error> {
+    DropOptions::new().drop_privileges_to(pwd)
+}
+
+fn prctl(option: libc::c_int, arg: libc::c_ulong) -> Result<(), io::Error> {
+    if unsafe { libc::prctl(option, arg, 0, 0, 0) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Sets the permitted and effective capability sets of the calling thread
+/// to capabilities, clearing the inheritable set.
+fn set_capabilities(capabilities: CapabilitySet) -> Result<(), io::Error> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); 2];
+    for (i, data) in data.iter_mut().enumerate() {
+        let bits = (capabilities.bits() >> (32 * i)) as u32;
+        data.effective = bits;
+        data.permitted = bits;
+    }
+
+    let return_code = unsafe {
+        libc::syscall(
+            libc::SYS_capset,
+            &mut header as *mut CapUserHeader,
+            data.as_ptr(),
+        )
+    };
+    if return_code == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the raw supplementary group ids of the calling process.
+///
+/// # libc functions used
+///
+/// - [`getgroups`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getgroups.html)
+pub(crate) fn get_groups() -> Result<Vec<libc::gid_t>, io::Error> {
+    loop {
+        let count = unsafe { libc::getgroups(0, ptr::null_mut()) };
+        if count == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut groups: Vec<libc::gid_t> = vec![0; count as usize];
+        let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+        if count >= 0 {
+            groups.truncate(count as usize);
+            return Ok(groups);
+        }
+
+        // Groups of process changed between the 2 calls
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINVAL) {
+            return Err(err);
+        }
+    }
+}
+
+/// Sets the supplementary group ids of the calling process.
+///
+/// # libc functions used
+///
+/// - [`setgroups`](https://man7.org/linux/man-pages/man2/setgroups.2.html)
+pub(crate) fn set_groups(groups: &[libc::gid_t]) -> Result<(), io::Error> {
+    if unsafe { libc::setgroups(groups.len(), groups.as_ptr()) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::panic;
+
+    use crate::os::unix::{GroupidBufExt, UseridExt};
+
+    /// Runs f in a forked child, so that identity changes do not affect other tests.
+    fn in_child(f: impl FnOnce() -> bool) -> bool {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            let ok = panic::catch_unwind(panic::AssertUnwindSafe(f)).unwrap_or(false);
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+        }
+
+        let mut wstatus = 0;
+        unsafe { libc::waitpid(pid, &mut wstatus, 0) };
+
+        libc::WIFEXITED(wstatus) && libc::WEXITSTATUS(wstatus) == 0
+    }
+
+    #[test]
+    fn test_identity_guard_assume_and_restore() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        assert!(in_child(|| {
+            let nobody: libc::uid_t = 65534;
+            let groups = [crate::GroupidBuf::from_raw_gid(65534)];
+            let guard = IdentityGuard::assume(
+                crate::Userid::from_raw_uid(&nobody),
+                crate::Groupid::from_raw_gid(&nobody),
+                &groups,
+            )
+            .unwrap();
+
+            let assumed = unsafe { libc::geteuid() == 65534 && libc::getegid() == 65534 }
+                && get_groups().unwrap() == [65534];
+            guard.restore().unwrap();
+
+            assumed && unsafe { libc::geteuid() == 0 && libc::getegid() == 0 }
+        }));
+    }
+
+    #[test]
+    fn test_identity_guard_assume_fails_unprivileged() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        assert!(in_child(|| {
+            let nobody: libc::uid_t = 65534;
+            unsafe { libc::setresuid(nobody, nobody, nobody) };
+            let uid = 0;
+
+            IdentityGuard::assume(
+                crate::Userid::from_raw_uid(&uid),
+                crate::Groupid::from_raw_gid(&uid),
+                &[],
+            )
+            .is_err()
+        }));
+    }
+
+    #[test]
+    fn test_drop_privileges_to_nobody() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        assert!(in_child(|| {
+            let pwd = crate::os::unix::get_pw_by_uid(65534).unwrap();
+            drop_privileges_to(&pwd).unwrap();
+
+            let dropped = unsafe { libc::getuid() == 65534 && libc::geteuid() == 65534 };
+            let regained = unsafe { libc::setuid(0) } != -1 || unsafe { libc::setgid(0) } != -1;
+
+            dropped && !regained && !get_groups().unwrap().contains(&0)
+        }));
+    }
+
+    #[test]
+    fn test_drop_privileges_keep_capabilities() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        assert!(in_child(|| {
+            let pwd = crate::os::unix::get_pw_by_uid(65534).unwrap();
+            let keep: CapabilitySet = [Capability::NetBindService].into_iter().collect();
+            DropOptions::new()
+                .keep_capabilities(keep)
+                .no_new_privs(true)
+                .drop_privileges_to(&pwd)
+                .unwrap();
+
+            let creds = crate::os::unix::ProcessCredentials::for_self().unwrap();
+            creds.has_capability(Capability::NetBindService)
+                && !creds.has_capability(Capability::Setuid)
+                && creds.no_new_privs()
+        }));
+    }
+}
@@ -0,0 +1,594 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+
+use crate::os::unix::{
+    get_pw_by_name, Capability, GroupidBufExt, ProcessCapabilities, UseridBufExt, UseridExt,
+};
+use crate::Error;
+
+/// The credentials of a process as found in `/proc/<pid>/status`.
+///
+/// See [`proc_pid_status(5)`](https://man7.org/linux/man-pages/man5/proc_pid_status.5.html).
+#[derive(Debug)]
+pub struct ProcessCredentials {
+    uids: [crate::UseridBuf; 4],
+    gids: [crate::GroupidBuf; 4],
+    groups: Vec<crate::GroupidBuf>,
+    no_new_privs: bool,
+    capabilities: ProcessCapabilities,
+}
+
+impl ProcessCredentials {
+    /// Reads the credentials of the process with the given pid.
+    ///
+    /// Returns an [`Error::Io`] with `ESRCH` if the process does not exist or exits
+    /// while its status is being read.
+    pub fn for_pid(pid: libc::pid_t) -> Result<Self, Error> {
+        Self::read(&format!("/proc/{}/status", pid))
+    }
+
+    /// Reads the credentials of the calling process through `/proc/self`.
+    pub fn for_self() -> Result<Self, Error> {
+        Self::read("/proc/self/status")
+    }
+
+    fn read(path: &str) -> Result<Self, Error> {
+        let status = fs::read_to_string(path).map_err(proc_error)?;
+
+        Self::from_status(&status).map_err(Error::Io)
+    }
+
+    pub(crate) fn from_status(status: &str) -> Result<Self, io::Error> {
+        let mut uids = None;
+        let mut gids = None;
+        let mut groups = None;
+        let mut no_new_privs = false;
+        let mut capabilities = ProcessCapabilities::default();
+
+        for (key, value) in status_fields(status) {
+            match key {
+                "Uid" => uids = Some(parse_id_quad(value)?.map(crate::UseridBuf::from_raw_uid)),
+                "Gid" => gids = Some(parse_id_quad(value)?.map(crate::GroupidBuf::from_raw_gid)),
+                "Groups" => {
+                    groups = Some(
+                        parse_ids(value)?
+                            .into_iter()
+                            .map(crate::GroupidBuf::from_raw_gid)
+                            .collect(),
+                    )
+                }
+                "NoNewPrivs" => no_new_privs = value == "1",
+                "CapInh" => capabilities.inheritable = value.parse()?,
+                "CapPrm" => capabilities.permitted = value.parse()?,
+                "CapEff" => capabilities.effective = value.parse()?,
+                "CapBnd" => capabilities.bounding = value.parse()?,
+                "CapAmb" => capabilities.ambient = value.parse()?,
+                _ => (),
+            }
+        }
+
+        match (uids, gids, groups) {
+            (Some(uids), Some(gids), Some(groups)) => Ok(Self {
+                uids,
+                gids,
+                groups,
+                no_new_privs,
+                capabilities,
+            }),
+            _ => Err(invalid_status()),
+        }
+    }
+
+    /// Returns the real user id of process.
+    #[inline]
+    pub fn real_uid(&self) -> &crate::Userid {
+        &self.uids[0]
+    }
+
+    /// Returns the effective user id of process.
+    #[inline]
+    pub fn effective_uid(&self) -> &crate::Userid {
+        &self.uids[1]
+    }
+
+    /// Returns the saved set-user-id of process.
+    #[inline]
+    pub fn saved_uid(&self) -> &crate::Userid {
+        &self.uids[2]
+    }
+
+    /// Returns the filesystem user id of process.
+    #[inline]
+    pub fn fs_uid(&self) -> &crate::Userid {
+        &self.uids[3]
+    }
+
+    /// Returns the real group id of process.
+    #[inline]
+    pub fn real_gid(&self) -> &crate::Groupid {
+        &self.gids[0]
+    }
+
+    /// Returns the effective group id of process.
+    #[inline]
+    pub fn effective_gid(&self) -> &crate::Groupid {
+        &self.gids[1]
+    }
+
+    /// Returns the saved set-group-id of process.
+    #[inline]
+    pub fn saved_gid(&self) -> &crate::Groupid {
+        &self.gids[2]
+    }
+
+    /// Returns the filesystem group id of process.
+    #[inline]
+    pub fn fs_gid(&self) -> &crate::Groupid {
+        &self.gids[3]
+    }
+
+    /// Returns the supplementary group ids of process.
+    #[inline]
+    pub fn groups(&self) -> &[crate::GroupidBuf] {
+        &self.groups
+    }
+
+    /// Returns whether the `no_new_privs` flag of process is set.
+    ///
+    /// Always `false` on kernels before 4.10, which do not report the flag.
+    #[inline]
+    pub fn no_new_privs(&self) -> bool {
+        self.no_new_privs
+    }
+
+    /// Returns the capability sets of process.
+    #[inline]
+    pub fn capabilities(&self) -> &ProcessCapabilities {
+        &self.capabilities
+    }
+
+    /// Checks whether capability is in the effective capability set of process.
+    #[inline]
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.effective.contains(capability)
+    }
+
+    /// Checks whether process is privileged, either by having an effective
+    /// user id of 0 or by having an effective capability of
+    /// [`Capability::ROOT_EQUIVALENT`].
+    ///
+    /// A root process that dropped all its capabilities is still privileged,
+    /// as it owns most of the files on the system. Narrow capabilities such as
+    /// `CAP_NET_BIND_SERVICE` alone do not make a process privileged.
+    pub fn is_effectively_privileged(&self) -> bool {
+        self.effective_uid().as_raw_uid() == 0
+            || Capability::ROOT_EQUIVALENT
+                .iter()
+                .any(|&capability| self.has_capability(capability))
+    }
+}
+
+/// A condition on the credentials of a process.
+#[derive(Debug)]
+pub enum ProcessFilter {
+    /// Matches processes whose real user id is the given user id.
+    RealUser(crate::UseridBuf),
+
+    /// Matches processes whose effective user id is the given user id.
+    EffectiveUser(crate::UseridBuf),
+
+    /// Matches processes whose real group id is the given group id.
+    RealGroup(crate::GroupidBuf),
+
+    /// Matches processes whose effective group id is the given group id.
+    EffectiveGroup(crate::GroupidBuf),
+}
+
+impl ProcessFilter {
+    /// Returns a filter matching processes whose effective user id is the user with login name.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`getpwnam_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getpwnam_r.html)
+    pub fn user_name(name: &OsStr) -> Result<Self, Error> {
+        let pwd = get_pw_by_name(name)?;
+
+        Ok(Self::EffectiveUser(crate::UseridBuf::from_raw_uid(
+            pwd.uid().as_raw_uid(),
+        )))
+    }
+
+    /// Checks whether the given credentials match filter.
+    pub fn matches(&self, credentials: &ProcessCredentials) -> bool {
+        match self {
+            Self::RealUser(userid) => credentials.real_uid() == &**userid,
+            Self::EffectiveUser(userid) => credentials.effective_uid() == &**userid,
+            Self::RealGroup(groupid) => credentials.real_gid() == &**groupid,
+            Self::EffectiveGroup(groupid) => credentials.effective_gid() == &**groupid,
+        }
+    }
+}
+
+/// A process found in `/proc`.
+#[derive(Debug)]
+pub struct ProcessInfo {
+    pid: libc::pid_t,
+    credentials: ProcessCredentials,
+    cmdline: Vec<OsString>,
+}
+
+impl ProcessInfo {
+    /// Reads the credentials and command line of the process with the given pid.
+    pub fn for_pid(pid: libc::pid_t) -> Result<Self, Error> {
+        let credentials = ProcessCredentials::for_pid(pid)?;
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).map_err(proc_error)?;
+
+        Ok(Self {
+            pid,
+            credentials,
+            cmdline: split_cmdline(cmdline),
+        })
+    }
+
+    /// Returns the pid of process.
+    #[inline]
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// Returns the credentials of process.
+    #[inline]
+    pub fn credentials(&self) -> &ProcessCredentials {
+        &self.credentials
+    }
+
+    /// Returns the command line arguments of process.
+    ///
+    /// Empty for kernel threads and zombie processes.
+    #[inline]
+    pub fn cmdline(&self) -> &[OsString] {
+        &self.cmdline
+    }
+}
+
+/// An iterator over the processes in `/proc`.
+///
+/// Processes that exit while being read are skipped.
+#[derive(Debug)]
+pub struct Processes {
+    read_dir: fs::ReadDir,
+    filters: Vec<ProcessFilter>,
+}
+
+impl Processes {
+    /// Creates a new `Processes` instance iterating over all processes.
+    pub fn new() -> Result<Self, io::Error> {
+        Ok(Self {
+            read_dir: fs::read_dir("/proc")?,
+            filters: Vec::new(),
+        })
+    }
+
+    /// Only yields processes matching filter, in addition to any previous filters.
+    pub fn filter_by(mut self, filter: ProcessFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+}
+
+impl Iterator for Processes {
+    type Item = Result<ProcessInfo, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.read_dir.next()? {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(Error::Io(err))),
+            };
+            let pid: libc::pid_t = match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+                Some(pid) => pid,
+                None => continue,
+            };
+
+            let credentials = match ProcessCredentials::for_pid(pid) {
+                Ok(credentials) => credentials,
+                Err(err) if is_no_process(&err) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+            if !self
+                .filters
+                .iter()
+                .all(|filter| filter.matches(&credentials))
+            {
+                continue;
+            }
+
+            match fs::read(format!("/proc/{}/cmdline", pid)).map_err(proc_error) {
+                Ok(cmdline) => {
+                    return Some(Ok(ProcessInfo {
+                        pid,
+                        credentials,
+                        cmdline: split_cmdline(cmdline),
+                    }))
+                }
+                Err(err) if is_no_process(&err) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Returns an iterator over the processes whose effective user id is the user with login name.
+///
+/// This is the equivalent of `pgrep -u name`.
+pub fn user_processes(name: &OsStr) -> Result<Processes, Error> {
+    let filter = ProcessFilter::user_name(name)?;
+
+    Ok(Processes::new()?.filter_by(filter))
+}
+
+/// Sends signal to every process whose real or effective user id is the given user id,
+/// except init and the calling process, and returns the pids signalled.
+///
+/// If `dry_run` is `true`, no signal is sent and the pids that would be signalled are returned.
+/// Processes that exit before being signalled are left out.
+///
+/// Signalling the processes of root affects every system service, so it is refused
+/// with an [`io::ErrorKind::InvalidInput`] error unless `allow_root` is `true`.
+///
+/// # libc functions used
+///
+/// - [`kill`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/kill.html)
+pub fn signal_user_processes(
+    userid: &crate::Userid,
+    signal: libc::c_int,
+    dry_run: bool,
+    allow_root: bool,
+) -> Result<Vec<libc::pid_t>, Error> {
+    if userid.as_raw_uid() == 0 && !allow_root {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "refusing to signal the processes of root",
+        )));
+    }
+
+    let self_pid = unsafe { libc::getpid() };
+    let mut pids = Vec::new();
+
+    for process in Processes::new()? {
+        let process = process?;
+        let credentials = process.credentials();
+
+        if process.pid() == 1
+            || process.pid() == self_pid
+            || (credentials.real_uid() != userid && credentials.effective_uid() != userid)
+        {
+            continue;
+        }
+
+        if !dry_run && unsafe { libc::kill(process.pid(), signal) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ESRCH) {
+                continue;
+            }
+            return Err(Error::Io(err));
+        }
+        pids.push(process.pid());
+    }
+
+    Ok(pids)
+}
+
+/// Returns the audit login user id of the process with the given pid, or `None`
+/// if it is unset, as for processes not started from a login session.
+///
+/// The login user id is set by `pam_loginuid(8)` at login and kept across
+/// `su(1)` and `sudo(8)`. It is also `None` if the kernel is built without
+/// audit support. Returns an [`Error::Io`] with `ESRCH` if the process does
+/// not exist.
+///
+/// See [`proc_pid_loginuid(5)`](https://man7.org/linux/man-pages/man5/proc_pid_loginuid.5.html).
+pub fn login_uid(pid: libc::pid_t) -> Result<Option<crate::UseridBuf>, Error> {
+    Ok(read_audit_id(pid, "loginuid")?.map(crate::UseridBuf::from_raw_uid))
+}
+
+/// Returns the audit session id of the process with the given pid, or `None`
+/// if it is unset, as for processes not started from a login session.
+///
+/// The session id is assigned when the login user id is set, and is also `None`
+/// if the kernel is built without audit support. Returns an [`Error::Io`] with
+/// `ESRCH` if the process does not exist.
+///
+/// See [`proc_pid_sessionid(5)`](https://man7.org/linux/man-pages/man5/proc_pid_sessionid.5.html).
+pub fn session_id(pid: libc::pid_t) -> Result<Option<u32>, Error> {
+    read_audit_id(pid, "sessionid")
+}
+
+/// Reads the audit id in `/proc/<pid>/<name>`, which is unset when it reads as `(u32)-1`.
+fn read_audit_id(pid: libc::pid_t, name: &str) -> Result<Option<u32>, Error> {
+    let dir = format!("/proc/{}", pid);
+    let id = match fs::read_to_string(format!("{}/{}", dir, name)) {
+        Ok(id) => id,
+        // Without audit support the file is missing, but the process exists
+        Err(err) if err.kind() == io::ErrorKind::NotFound && Path::new(&dir).exists() => {
+            return Ok(None)
+        }
+        Err(err) => return Err(proc_error(err)),
+    };
+    let id: u32 = id.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid /proc/<pid>/{}", name),
+        )
+    })?;
+
+    Ok(Some(id).filter(|&id| id != u32::MAX))
+}
+
+/// Converts an error from reading a file under `/proc/<pid>` into an [`Error`],
+/// reporting a process that does not exist or has exited as `ESRCH`.
+pub(crate) fn proc_error(err: io::Error) -> Error {
+    if err.kind() == io::ErrorKind::NotFound {
+        Error::Io(io::Error::from_raw_os_error(libc::ESRCH))
+    } else {
+        Error::Io(err)
+    }
+}
+
+/// Checks whether err reports a process that does not exist or has exited.
+pub(crate) fn is_no_process(err: &Error) -> bool {
+    matches!(err, Error::Io(err) if err.raw_os_error() == Some(libc::ESRCH))
+}
+
+/// Returns an iterator over the `Key:\tvalue` lines of a `/proc/<pid>/status` file.
+pub(crate) fn status_fields(status: &str) -> impl Iterator<Item = (&str, &str)> {
+    status
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key, value.trim()))
+}
+
+fn parse_ids(value: &str) -> Result<Vec<u32>, io::Error> {
+    value
+        .split_whitespace()
+        .map(|id| id.parse().map_err(|_| invalid_status()))
+        .collect()
+}
+
+fn parse_id_quad(value: &str) -> Result<[u32; 4], io::Error> {
+    match parse_ids(value)?.as_slice() {
+        &[real, effective, saved, fs] => Ok([real, effective, saved, fs]),
+        _ => Err(invalid_status()),
+    }
+}
+
+fn split_cmdline(cmdline: Vec<u8>) -> Vec<OsString> {
+    cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| OsString::from_vec(arg.to_vec()))
+        .collect()
+}
+
+fn invalid_status() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid /proc/<pid>/status")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::unix::GroupidExt;
+
+    #[test]
+    fn test_from_status() {
+        let status = "Name:\tcat\nUid:\t1000\t0\t1001\t1002\nGid:\t100\t101\t102\t103\n\
+                      Groups:\t4 24 27 \nNoNewPrivs:\t1\nCapInh:\t0000000000000000\n\
+                      CapPrm:\t0000000000000002\nCapEff:\t0000000000000002\n\
+                      CapBnd:\t000001ffffffffff\nCapAmb:\t0000000000000000\n";
+        let creds = ProcessCredentials::from_status(status).unwrap();
+
+        assert_eq!(creds.real_uid().as_raw_uid(), 1000);
+        assert_eq!(creds.effective_uid().as_raw_uid(), 0);
+        assert_eq!(creds.saved_uid().as_raw_uid(), 1001);
+        assert_eq!(creds.fs_uid().as_raw_uid(), 1002);
+        assert_eq!(creds.real_gid().as_raw_gid(), 100);
+        assert_eq!(creds.fs_gid().as_raw_gid(), 103);
+        assert_eq!(creds.groups().len(), 3);
+        assert!(creds.no_new_privs());
+        assert!(creds.has_capability(Capability::DacOverride));
+        assert!(!creds.has_capability(Capability::Chown));
+        assert!(creds
+            .capabilities()
+            .bounding()
+            .contains(Capability::SysAdmin));
+        assert!(creds.capabilities().ambient().is_empty());
+        assert!(creds.is_effectively_privileged());
+
+        let status = "Uid:\t1000\t1000\t1000\t1000\nGid:\t100\t100\t100\t100\n\
+                      Groups:\t\nCapEff:\t0000000000000400\n";
+        let creds = ProcessCredentials::from_status(status).unwrap();
+
+        assert!(creds.has_capability(Capability::NetBindService));
+        assert!(!creds.is_effectively_privileged());
+    }
+
+    #[test]
+    fn test_login_uid_session_id() {
+        let pid = unsafe { libc::getpid() };
+        let uid = login_uid(pid).unwrap();
+        let session = session_id(pid).unwrap();
+
+        // The session id is assigned along with the login user id
+        assert_eq!(uid.is_some(), session.is_some());
+        assert!(matches!(login_uid(-1), Err(ref err) if is_no_process(err)));
+        assert!(matches!(session_id(-1), Err(ref err) if is_no_process(err)));
+    }
+
+    #[test]
+    fn test_for_self() {
+        let creds = ProcessCredentials::for_self().unwrap();
+
+        assert_eq!(creds.real_uid().as_raw_uid(), unsafe { libc::getuid() });
+        assert_eq!(creds.effective_gid().as_raw_gid(), unsafe {
+            libc::getegid()
+        });
+
+        let creds = ProcessCredentials::for_pid(unsafe { libc::getpid() }).unwrap();
+        assert_eq!(creds.effective_uid().as_raw_uid(), unsafe {
+            libc::geteuid()
+        });
+    }
+
+    #[test]
+    fn test_for_pid_noprocess() {
+        let result = ProcessCredentials::for_pid(libc::pid_t::MAX);
+
+        assert!(matches!(result, Err(ref err) if is_no_process(err)));
+    }
+
+    #[test]
+    fn test_processes_filter_self() {
+        let uid = unsafe { libc::geteuid() };
+        let userid = crate::UseridBuf::from_raw_uid(uid);
+        let self_pid = unsafe { libc::getpid() };
+
+        let found = Processes::new()
+            .unwrap()
+            .filter_by(ProcessFilter::EffectiveUser(userid))
+            .filter_map(Result::ok)
+            .find(|process| process.pid() == self_pid)
+            .unwrap();
+
+        assert_eq!(found.credentials().effective_uid().as_raw_uid(), uid);
+        assert!(!found.cmdline().is_empty());
+    }
+
+    #[test]
+    fn test_signal_user_processes() {
+        let uid = unsafe { libc::geteuid() };
+        let userid = crate::Userid::from_raw_uid(&uid);
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+
+        // Signal 0 only checks that the processes can be signalled
+        let result = signal_user_processes(userid, 0, false, false);
+        let dry_run = signal_user_processes(userid, 0, true, true).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        if uid == 0 {
+            assert!(result.is_err());
+        } else {
+            assert!(result.unwrap().contains(&(child.id() as libc::pid_t)));
+        }
+        assert!(dry_run.contains(&(child.id() as libc::pid_t)));
+        assert!(!dry_run.contains(&unsafe { libc::getpid() }));
+        assert!(!dry_run.contains(&1));
+    }
+}
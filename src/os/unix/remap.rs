@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+
+use crate::os::unix::fs::walk_tree_at;
+use crate::os::unix::{
+    get_gr_by_name, get_pw_by_name, GroupidBufExt, GroupidExt, UseridBufExt, UseridExt,
+};
+use crate::Error;
+
+/// A translation table rewriting the owner of every file in a tree, such as
+/// after renumbering a user or importing a tree from another host.
+///
+/// Ids without a translation are left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct OwnershipRemap {
+    uids: HashMap<libc::uid_t, libc::uid_t>,
+    gids: HashMap<libc::gid_t, libc::gid_t>,
+    one_file_system: bool,
+}
+
+impl OwnershipRemap {
+    /// Creates a new `OwnershipRemap` instance without any translation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `OwnershipRemap` instance translating the ids of the users
+    /// and groups in the given `passwd(5)` and `group(5)` files to the ids of
+    /// the users and groups with the same names in the user and group database.
+    ///
+    /// Names without a record in the user or group database are left unmapped.
+    pub fn from_databases<P: AsRef<Path>, G: AsRef<Path>>(
+        source_passwd: P,
+        source_group: G,
+    ) -> Result<Self, Error> {
+        let mut remap = Self::new();
+
+        for (name, uid) in parse_db_file(&fs::read(source_passwd)?) {
+            match get_pw_by_name(&name) {
+                Ok(pwd) => {
+                    remap.map_uid(crate::Userid::from_raw_uid(&uid), pwd.uid());
+                }
+                Err(Error::NoRecord) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        for (name, gid) in parse_db_file(&fs::read(source_group)?) {
+            match get_gr_by_name(&name) {
+                Ok(grp) => {
+                    remap.map_gid(crate::Groupid::from_raw_gid(&gid), grp.gid());
+                }
+                Err(Error::NoRecord) => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(remap)
+    }
+
+    /// Translates files owned by user id `from` to user id `to`.
+    pub fn map_uid(&mut self, from: &crate::Userid, to: &crate::Userid) -> &mut Self {
+        self.uids.insert(from.as_raw_uid(), to.as_raw_uid());
+        self
+    }
+
+    /// Translates files owned by group id `from` to group id `to`.
+    pub fn map_gid(&mut self, from: &crate::Groupid, to: &crate::Groupid) -> &mut Self {
+        self.gids.insert(from.as_raw_gid(), to.as_raw_gid());
+        self
+    }
+
+    /// Sets whether directories on other file systems are not descended into.
+    pub fn one_file_system(&mut self, one_file_system: bool) -> &mut Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Returns the translation of user id, if any.
+    pub fn uid(&self, uid: &crate::Userid) -> Option<crate::UseridBuf> {
+        self.uids
+            .get(&uid.as_raw_uid())
+            .map(|&uid| crate::UseridBuf::from_raw_uid(uid))
+    }
+
+    /// Returns the translation of group id, if any.
+    pub fn gid(&self, gid: &crate::Groupid) -> Option<crate::GroupidBuf> {
+        self.gids
+            .get(&gid.as_raw_gid())
+            .map(|&gid| crate::GroupidBuf::from_raw_gid(gid))
+    }
+
+    /// Rewrites the owner of every file in the tree rooted at path, without
+    /// following symbolic links.
+    ///
+    /// Files are changed relative to their directory, so that swapping a
+    /// directory of the tree for a symbolic link while walking it cannot change
+    /// the owner of files outside of it. Files with multiple hard links are
+    /// rewritten once. The set-user-id and set-group-id bits cleared by the
+    /// kernel when the owner of a file changes are restored afterwards.
+    ///
+    /// Stops at the first error, leaving the files visited before it changed
+    /// and the rest of the tree unchanged.
+    pub fn remap_tree<P: AsRef<Path>>(&self, path: P) -> Result<RemapSummary, io::Error> {
+        let mut summary = RemapSummary::default();
+        let mut seen_inodes = HashSet::new();
+
+        walk_tree_at(path.as_ref(), self.one_file_system, &mut |entry| {
+            summary.files_scanned += 1;
+
+            // Rewriting a hard link again would translate the rewritten owner
+            if !entry.is_dir() && entry.nlink() > 1 && !seen_inodes.insert(entry.dev_ino()) {
+                return Ok(());
+            }
+
+            let uid = self.uids.get(&entry.uid()).copied();
+            let gid = self.gids.get(&entry.gid()).copied();
+            if uid.is_none() && gid.is_none() {
+                return Ok(());
+            }
+
+            entry.set_owner(
+                uid.unwrap_or(libc::uid_t::MAX),
+                gid.unwrap_or(libc::gid_t::MAX),
+                false,
+            )?;
+            summary.files_changed += 1;
+            summary.uids_changed += uid.is_some() as u64;
+            summary.gids_changed += gid.is_some() as u64;
+
+            if entry.restore_setid_bits()? {
+                summary.setid_restored += 1;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(summary)
+    }
+}
+
+/// A summary of the changes made by [`OwnershipRemap::remap_tree`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemapSummary {
+    files_scanned: u64,
+    files_changed: u64,
+    uids_changed: u64,
+    gids_changed: u64,
+    setid_restored: u64,
+}
+
+impl RemapSummary {
+    /// Returns the number of files found in tree.
+    #[inline]
+    pub fn files_scanned(&self) -> u64 {
+        self.files_scanned
+    }
+
+    /// Returns the number of files whose owner was rewritten.
+    #[inline]
+    pub fn files_changed(&self) -> u64 {
+        self.files_changed
+    }
+
+    /// Returns the number of files whose user id was rewritten.
+    #[inline]
+    pub fn uids_changed(&self) -> u64 {
+        self.uids_changed
+    }
+
+    /// Returns the number of files whose group id was rewritten.
+    #[inline]
+    pub fn gids_changed(&self) -> u64 {
+        self.gids_changed
+    }
+
+    /// Returns the number of files whose set-user-id or set-group-id bits were restored.
+    #[inline]
+    pub fn setid_restored(&self) -> u64 {
+        self.setid_restored
+    }
+}
+
+/// Returns the name and id of every record in a `passwd(5)` or `group(5)` file,
+/// which both hold the id in their third field.
+fn parse_db_file(contents: &[u8]) -> Vec<(OsString, u32)> {
+    contents
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.starts_with(b"#"))
+        .filter_map(|line| {
+            let mut fields = line.split(|&b| b == b':');
+            let name = fields.next().filter(|name| !name.is_empty())?;
+            let id = std::str::from_utf8(fields.nth(1)?).ok()?.parse().ok()?;
+
+            Some((OsString::from_vec(name.to_vec()), id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use crate::os::unix::set_owner_nofollow;
+    use crate::os::unix::test_util::TempDir;
+
+    #[test]
+    fn test_parse_db_file() {
+        let records =
+            parse_db_file(b"root:x:0:0:root:/root:/bin/sh\n# comment\nstaff:x:50:\nbad\n");
+
+        assert_eq!(
+            records,
+            [(OsString::from("root"), 0), (OsString::from("staff"), 50)]
+        );
+    }
+
+    #[test]
+    fn test_remap_tree() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = TempDir::new("remap");
+        fs::write(dir.join("passwd"), "root:x:5000:5000::/root:/bin/sh\n").unwrap();
+        fs::write(dir.join("group"), "root:x:5000:\n").unwrap();
+        fs::write(dir.join("setuid"), b"").unwrap();
+        set_owner_nofollow(
+            dir.join("setuid"),
+            Some(crate::Userid::from_raw_uid(&5000)),
+            Some(crate::Groupid::from_raw_gid(&5000)),
+        )
+        .unwrap();
+        fs::set_permissions(dir.join("setuid"), fs::Permissions::from_mode(0o4755)).unwrap();
+
+        let remap = OwnershipRemap::from_databases(dir.join("passwd"), dir.join("group")).unwrap();
+        let summary = remap.remap_tree(dir.path()).unwrap();
+        let metadata = fs::metadata(dir.join("setuid")).unwrap();
+
+        assert_eq!(summary.files_scanned(), 4);
+        assert_eq!(summary.files_changed(), 1);
+        assert_eq!((metadata.uid(), metadata.gid()), (0, 0));
+        assert_eq!(metadata.mode() & 0o7777, 0o4755);
+    }
+
+    #[test]
+    fn test_remap_tree_hardlink_swap() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = TempDir::new("remap-swap");
+        fs::write(dir.join("file"), b"").unwrap();
+        fs::hard_link(dir.join("file"), dir.join("link")).unwrap();
+        set_owner_nofollow(
+            dir.join("file"),
+            Some(crate::Userid::from_raw_uid(&5001)),
+            None,
+        )
+        .unwrap();
+
+        let (a, b) = (
+            crate::Userid::from_raw_uid(&5001),
+            crate::Userid::from_raw_uid(&5002),
+        );
+        let summary = OwnershipRemap::new()
+            .map_uid(a, b)
+            .map_uid(b, a)
+            .remap_tree(dir.path())
+            .unwrap();
+
+        assert_eq!(summary.files_changed(), 1);
+        assert_eq!(fs::metadata(dir.join("link")).unwrap().uid(), 5002);
+    }
+}
@@ -0,0 +1,389 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::panic;
+use std::path::PathBuf;
+
+use crate::os::unix::privilege::{DropError, DropOptions};
+use crate::os::unix::{get_group_list, GroupidExt, Passwd, UseridExt};
+use crate::Error;
+
+/// A value that can be sent from the child process of [`run_as`] to its parent.
+pub trait Transfer: Sized {
+    /// Encodes value into bytes.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decodes value from bytes produced by [`Transfer::encode`].
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Transfer for () {
+    fn encode(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes.is_empty().then_some(())
+    }
+}
+
+impl Transfer for bool {
+    fn encode(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_transfer_for_int {
+    ($($int:ty)*) => {
+        $(
+            impl Transfer for $int {
+                fn encode(&self) -> Vec<u8> {
+                    self.to_ne_bytes().to_vec()
+                }
+
+                fn decode(bytes: &[u8]) -> Option<Self> {
+                    bytes.try_into().ok().map(<$int>::from_ne_bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_transfer_for_int! { u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize }
+
+impl Transfer for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl Transfer for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl Transfer for OsString {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(OsString::from_vec(bytes.to_vec()))
+    }
+}
+
+impl Transfer for PathBuf {
+    fn encode(&self) -> Vec<u8> {
+        self.as_os_str().as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        OsString::decode(bytes).map(PathBuf::from)
+    }
+}
+
+/// Tag of a message holding the encoded return value of the closure.
+const TAG_OK: u8 = 0;
+
+/// Tag of a message holding an error of the closure, as a raw os error code,
+/// or -1 followed by the index of its kind in `ERROR_KINDS` and its message.
+const TAG_ERR: u8 = 1;
+
+/// Tag of a message holding a failure to drop privileges, as a raw [`DropError`].
+const TAG_DROP_ERR: u8 = 2;
+
+/// The error kinds that survive the trip from the child to the parent.
+const ERROR_KINDS: &[io::ErrorKind] = &[
+    io::ErrorKind::Other,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::NotConnected,
+    io::ErrorKind::AddrInUse,
+    io::ErrorKind::AddrNotAvailable,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::AlreadyExists,
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::WriteZero,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::Unsupported,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::OutOfMemory,
+];
+
+/// Runs closure as user in a forked child process and returns its result.
+///
+/// The supplementary groups of user are looked up before forking. The child
+/// then permanently drops its privileges to user as
+/// [`drop_privileges_to`](crate::os::unix::drop_privileges_to) does, using only
+/// async-signal-safe system calls, runs the closure and sends the returned value
+/// or error back through a pipe. The credentials of the calling process are never
+/// changed, which usually requires the calling process to have an effective
+/// user id of 0.
+///
+/// The child of a multi-threaded process only has the calling thread, so the
+/// closure must not wait on locks that other threads may have held at the time
+/// of the fork. A child that panics, is killed by a signal or fails to send its
+/// result is reported as an error.
+///
+/// # libc functions used
+///
+/// - [`getgrouplist`](https://man7.org/linux/man-pages/man3/getgrouplist.3.html)
+/// - [`pipe2`](https://man7.org/linux/man-pages/man2/pipe2.2.html)
+/// - [`fork`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/fork.html)
+/// - [`waitpid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/waitpid.html)
+pub fn run_as<T, F>(pwd: &Passwd, f: F) -> Result<T, Error>
+where
+    T: Transfer,
+    F: FnOnce() -> Result<T, io::Error>,
+{
+    let uid = pwd.uid().as_raw_uid();
+    let gid = pwd.gid().as_raw_gid();
+    let groups = get_group_list(pwd.name(), gid)?;
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(Error::last_os_error());
+    }
+    // SAFETY: both fds are newly created by pipe2 and owned by nothing else
+    let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(Error::last_os_error());
+    }
+    if pid == 0 {
+        drop(reader);
+        if let Err(err) = DropOptions::new().drop_to_ids(uid, gid, &groups) {
+            let mut message = [0; 5];
+            message[0] = TAG_DROP_ERR;
+            message[1..].copy_from_slice(&err.to_raw().to_ne_bytes());
+            let written = unsafe {
+                libc::write(
+                    writer.as_raw_fd(),
+                    message.as_ptr() as *const libc::c_void,
+                    message.len(),
+                )
+            };
+            unsafe {
+                libc::_exit(if written == message.len() as isize {
+                    0
+                } else {
+                    1
+                })
+            };
+        }
+        run_child(f, writer);
+    }
+    drop(writer);
+
+    let mut message = Vec::new();
+    let read_result = reader.read_to_end(&mut message);
+    let mut wstatus = 0;
+    while unsafe { libc::waitpid(pid, &mut wstatus, 0) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(Error::Io(err));
+        }
+    }
+    read_result?;
+
+    if libc::WIFSIGNALED(wstatus) {
+        return Err(child_error(format!(
+            "child terminated by signal {}",
+            libc::WTERMSIG(wstatus)
+        )));
+    }
+    if !libc::WIFEXITED(wstatus) || libc::WEXITSTATUS(wstatus) != 0 {
+        return Err(child_error(format!(
+            "child exited with status {}",
+            libc::WEXITSTATUS(wstatus)
+        )));
+    }
+
+    decode_message(&message)
+}
+
+fn run_child<T, F>(f: F, mut writer: File) -> !
+where
+    T: Transfer,
+    F: FnOnce() -> Result<T, io::Error>,
+{
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(f));
+
+    let message = match result {
+        Ok(Ok(value)) => {
+            let mut message = vec![TAG_OK];
+            message.extend(value.encode());
+            message
+        }
+        Ok(Err(err)) => {
+            let mut message = vec![TAG_ERR];
+            match err.raw_os_error() {
+                Some(code) => message.extend(code.to_ne_bytes()),
+                None => {
+                    let kind = ERROR_KINDS.iter().position(|&kind| kind == err.kind());
+                    message.extend((-1i32).to_ne_bytes());
+                    message.push(kind.unwrap_or(0) as u8);
+                    message.extend(err.to_string().into_bytes());
+                }
+            }
+            message
+        }
+        // Exit with the status of a panicking Rust program
+        Err(_) => unsafe { libc::_exit(101) },
+    };
+
+    let exit_code = match writer.write_all(&message) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    // Exit without running destructors or atexit handlers of the parent
+    unsafe { libc::_exit(exit_code) }
+}
+
+fn decode_message<T: Transfer>(message: &[u8]) -> Result<T, Error> {
+    match message.split_first() {
+        Some((&TAG_OK, bytes)) => {
+            T::decode(bytes).ok_or_else(|| child_error("invalid value from child"))
+        }
+        Some((&TAG_ERR, bytes)) if bytes.len() >= 4 => {
+            let (code, rest) = bytes.split_at(4);
+            let code = i32::from_ne_bytes(code.try_into().unwrap());
+
+            match rest.split_first() {
+                _ if code >= 0 => Err(Error::Io(io::Error::from_raw_os_error(code))),
+                Some((&kind, msg)) => {
+                    let kind = ERROR_KINDS
+                        .get(kind as usize)
+                        .copied()
+                        .unwrap_or(io::ErrorKind::Other);
+                    Err(Error::Io(io::Error::new(
+                        kind,
+                        String::from_utf8_lossy(msg).into_owned(),
+                    )))
+                }
+                None => Err(child_error("invalid error from child")),
+            }
+        }
+        Some((&TAG_DROP_ERR, bytes)) if bytes.len() == 4 => {
+            let raw = i32::from_ne_bytes(bytes.try_into().unwrap());
+
+            Err(Error::Io(DropError::from_raw(raw).into()))
+        }
+        _ => Err(child_error("child exited without a result")),
+    }
+}
+
+fn child_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: E) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::unix::get_pw_by_uid;
+
+    #[test]
+    fn test_transfer_roundtrip() {
+        assert_eq!(u32::decode(&42u32.encode()), Some(42));
+        assert_eq!(bool::decode(&true.encode()), Some(true));
+        assert_eq!(
+            String::decode(&String::from("alice").encode()),
+            Some(String::from("alice"))
+        );
+        assert_eq!(u64::decode(&[1, 2]), None);
+    }
+
+    #[test]
+    fn test_run_as_nobody() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let pwd = get_pw_by_uid(65534).unwrap();
+        let euid = run_as(&pwd, || Ok(unsafe { libc::geteuid() })).unwrap();
+
+        assert_eq!(euid, 65534);
+        assert_eq!(unsafe { libc::geteuid() }, 0);
+    }
+
+    #[test]
+    fn test_run_as_error() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let pwd = get_pw_by_uid(65534).unwrap();
+        let result: Result<(), Error> = run_as(&pwd, || {
+            File::create("/root/user_utils-run-as-test").map(|_| ())
+        });
+
+        assert!(
+            matches!(result, Err(Error::Io(err)) if err.kind() == io::ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_run_as_error_kind_and_signal() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let pwd = get_pw_by_uid(65534).unwrap();
+        let result: Result<(), Error> = run_as(&pwd, || {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "bad data"))
+        });
+        assert!(matches!(result, Err(Error::Io(ref err))
+            if err.kind() == io::ErrorKind::InvalidData && err.to_string() == "bad data"));
+
+        let result: Result<(), Error> = run_as(&pwd, || unsafe {
+            libc::raise(libc::SIGKILL);
+            Ok(())
+        });
+        assert!(matches!(result, Err(Error::Io(ref err)) if err.to_string().contains("signal 9")));
+    }
+
+    #[test]
+    fn test_decode_drop_error() {
+        let mut message = vec![TAG_DROP_ERR];
+        message.extend(DropError::UidRegained.to_raw().to_ne_bytes());
+        let result: Result<(), Error> = decode_message(&message);
+
+        assert!(matches!(result, Err(Error::Io(ref err))
+            if err.kind() == io::ErrorKind::PermissionDenied));
+
+        let mut message = vec![TAG_DROP_ERR];
+        message.extend(libc::EPERM.to_ne_bytes());
+        let result: Result<(), Error> = decode_message(&message);
+
+        assert!(
+            matches!(result, Err(Error::Io(ref err)) if err.raw_os_error() == Some(libc::EPERM))
+        );
+    }
+}
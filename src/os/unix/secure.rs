@@ -0,0 +1,383 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::os::unix::fs::resolve_path;
+use crate::os::unix::{
+    effective_uid, path_owner_nofollow, MetadataOwnerExt, UseridBufExt, UseridExt,
+};
+
+/// The way a file makes a path insecure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The file is not owned by any of the trusted owners.
+    UntrustedOwner,
+
+    /// The file is writable by its group.
+    GroupWritable,
+
+    /// The file is writable by everyone.
+    WorldWritable,
+}
+
+/// A file making a path insecure, found by [`check_secure_path`].
+#[derive(Debug)]
+pub struct PathViolation {
+    path: PathBuf,
+    kind: ViolationKind,
+    owner: crate::UseridBuf,
+    mode: u32,
+}
+
+impl PathViolation {
+    /// Returns the path of the offending file, which is the checked file, one of
+    /// the directories it is resolved through, or a symbolic link on the way.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the way the file makes the path insecure.
+    #[inline]
+    pub fn kind(&self) -> ViolationKind {
+        self.kind
+    }
+
+    /// Returns the user id owning the file.
+    #[inline]
+    pub fn owner(&self) -> &crate::Userid {
+        &self.owner
+    }
+
+    /// Returns the permission bits of the file.
+    #[inline]
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+}
+
+/// Checks that the file at path and every directory it is resolved through are
+/// owned by one of the trusted owners and cannot be written by anyone else,
+/// like the `StrictModes` check of OpenSSH, and returns the violations found.
+///
+/// An empty list means the path is secure. Symbolic links are followed, and
+/// the directories traversed through them are checked too; the links themselves
+/// must be owned by a trusted owner. Like `StrictModes`, directories with the
+/// sticky bit set, such as `/tmp`, are not exempt from the writability checks.
+///
+/// # libc functions used
+///
+/// - [`lstat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/lstat.html)
+pub fn check_secure_path<P: AsRef<Path>>(
+    path: P,
+    trusted_owners: &[&crate::Userid],
+) -> Result<Vec<PathViolation>, io::Error> {
+    let path = env::current_dir()?.join(path);
+    let mut violations = Vec::new();
+    let mut checked = HashSet::new();
+
+    let mut check = |path: &Path, metadata: &fs::Metadata| {
+        // A directory can be traversed more than once through symbolic links
+        if !checked.insert(path.to_path_buf()) {
+            return;
+        }
+
+        let owner = metadata.owner();
+        let mode = metadata.mode() & 0o7777;
+        let mut violation = |kind| {
+            violations.push(PathViolation {
+                path: path.to_path_buf(),
+                kind,
+                owner: metadata.owner(),
+                mode,
+            })
+        };
+
+        if !trusted_owners.iter().any(|trusted| **trusted == *owner) {
+            violation(ViolationKind::UntrustedOwner);
+        }
+        // The permission bits of symbolic links are meaningless
+        if metadata.file_type().is_symlink() {
+            return;
+        }
+        if mode & 0o020 != 0 {
+            violation(ViolationKind::GroupWritable);
+        }
+        if mode & 0o002 != 0 {
+            violation(ViolationKind::WorldWritable);
+        }
+    };
+
+    let (path, metadata) = resolve_path(&path, &mut |path, metadata| {
+        check(path, metadata);
+        Ok(true)
+    })?
+    .expect("resolution is never stopped");
+    check(&path, &metadata);
+
+    Ok(violations)
+}
+
+/// The reason the owner of a directory is trusted by an [`OwnershipCheck`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnerTrust {
+    /// The directory is owned by the effective user id of the calling process.
+    EffectiveUser,
+
+    /// The directory is owned by root.
+    Root,
+
+    /// The calling process runs as root through `sudo(8)`, and the directory is
+    /// owned by the user id in the `SUDO_UID` environment variable.
+    SudoUser,
+
+    /// The directory is owned by a user id of the allow-list.
+    AllowList,
+}
+
+/// The outcome of [`OwnershipCheck::check`].
+#[derive(Debug)]
+pub struct OwnershipVerdict {
+    owner: crate::UseridBuf,
+    trust: Option<OwnerTrust>,
+}
+
+impl OwnershipVerdict {
+    /// Returns the user id owning the directory.
+    #[inline]
+    pub fn owner(&self) -> &crate::Userid {
+        &self.owner
+    }
+
+    /// Returns the reason the owner is trusted, or `None` if the ownership is dubious.
+    #[inline]
+    pub fn trust(&self) -> Option<OwnerTrust> {
+        self.trust
+    }
+
+    /// Returns whether the owner is trusted.
+    #[inline]
+    pub fn is_trusted(&self) -> bool {
+        self.trust.is_some()
+    }
+}
+
+/// A check that a directory is owned by a trusted user before its contents are
+/// trusted, like the `safe.directory` check of git against repositories of
+/// dubious ownership.
+///
+/// By default, directories owned by the effective user id of the calling
+/// process or by root are trusted, and so are directories owned by the user
+/// id in `SUDO_UID` when the calling process runs as root.
+#[derive(Debug)]
+pub struct OwnershipCheck {
+    allowed: Vec<crate::UseridBuf>,
+    trust_root: bool,
+    honor_sudo: bool,
+}
+
+impl OwnershipCheck {
+    /// Creates a new `OwnershipCheck` instance with an empty allow-list.
+    pub fn new() -> Self {
+        Self {
+            allowed: Vec::new(),
+            trust_root: true,
+            honor_sudo: true,
+        }
+    }
+
+    /// Adds a user id to the allow-list.
+    pub fn allow(&mut self, uid: &crate::Userid) -> &mut Self {
+        self.allowed
+            .push(crate::UseridBuf::from_raw_uid(uid.as_raw_uid()));
+        self
+    }
+
+    /// Sets whether directories owned by root are trusted.
+    pub fn trust_root(&mut self, trust_root: bool) -> &mut Self {
+        self.trust_root = trust_root;
+        self
+    }
+
+    /// Sets whether the user id in the `SUDO_UID` environment variable is
+    /// trusted when the calling process runs as root.
+    ///
+    /// Environment variables can be forged by whoever starts the process, which
+    /// only matters if root itself is not trusted.
+    pub fn honor_sudo(&mut self, honor_sudo: bool) -> &mut Self {
+        self.honor_sudo = honor_sudo;
+        self
+    }
+
+    /// Checks the owner of the directory at path, without following symbolic
+    /// links, like git does: a symbolic link is judged by its own owner rather
+    /// than by the owner of its target.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`lstat`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/lstat.html)
+    /// - [`geteuid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/geteuid.html)
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> Result<OwnershipVerdict, io::Error> {
+        let sudo_uid = env::var_os("SUDO_UID")
+            .and_then(|uid| uid.to_str()?.parse::<libc::uid_t>().ok())
+            .map(crate::UseridBuf::from_raw_uid);
+
+        self.check_as(path.as_ref(), &effective_uid(), sudo_uid.as_deref())
+    }
+
+    fn check_as(
+        &self,
+        path: &Path,
+        euid: &crate::Userid,
+        sudo_uid: Option<&crate::Userid>,
+    ) -> Result<OwnershipVerdict, io::Error> {
+        let owner = path_owner_nofollow(path)?;
+        let owner = owner.uid();
+        let root = crate::Userid::from_raw_uid(&0);
+
+        let trust = if *owner == *euid {
+            Some(OwnerTrust::EffectiveUser)
+        } else if self.trust_root && *owner == *root {
+            Some(OwnerTrust::Root)
+        } else if self.honor_sudo && *euid == *root && sudo_uid == Some(owner) {
+            Some(OwnerTrust::SudoUser)
+        } else if self.allowed.iter().any(|allowed| **allowed == *owner) {
+            Some(OwnerTrust::AllowList)
+        } else {
+            None
+        };
+
+        Ok(OwnershipVerdict {
+            owner: crate::UseridBuf::from_raw_uid(owner.as_raw_uid()),
+            trust,
+        })
+    }
+}
+
+impl Default for OwnershipCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use crate::os::unix::test_util::TempDir;
+    use crate::os::unix::{set_owner_nofollow, UseridExt};
+
+    #[test]
+    fn test_check_secure_path() {
+        let root = crate::Userid::from_raw_uid(&0);
+        let euid = unsafe { libc::geteuid() };
+        let trusted = [root, crate::Userid::from_raw_uid(&euid)];
+
+        let dir = TempDir::new("secure");
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("config"), b"").unwrap();
+        fs::set_permissions(dir.join("config"), fs::Permissions::from_mode(0o644)).unwrap();
+        symlink("config", dir.join("link")).unwrap();
+        // The temporary directory holding dir is usually world-writable
+        let check = |path, trusted: &[&crate::Userid]| {
+            let mut violations = check_secure_path(path, trusted).unwrap();
+            violations.retain(|violation| violation.path().starts_with(dir.path()));
+            violations
+        };
+
+        assert!(check(dir.join("link"), &trusted).is_empty());
+
+        fs::set_permissions(dir.join("config"), fs::Permissions::from_mode(0o646)).unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o775)).unwrap();
+        let violations = check(dir.join("link"), &trusted);
+
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].path(), dir.path());
+        assert_eq!(violations[0].kind(), ViolationKind::GroupWritable);
+        assert_eq!(violations[1].path(), dir.join("config"));
+        assert_eq!(violations[1].kind(), ViolationKind::WorldWritable);
+        assert_eq!(violations[1].mode(), 0o646);
+
+        // Sticky directories are not exempt
+        fs::set_permissions(dir.join("config"), fs::Permissions::from_mode(0o644)).unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o1777)).unwrap();
+        let violations = check(dir.join("config"), &trusted);
+
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[1].kind(), ViolationKind::WorldWritable);
+        assert_eq!(violations[1].mode(), 0o1777);
+
+        if euid == 0 {
+            set_owner_nofollow(
+                dir.join("link"),
+                Some(crate::Userid::from_raw_uid(&65534)),
+                None,
+            )
+            .unwrap();
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+            let violations = check(dir.join("link"), &[root]);
+
+            assert_eq!(violations.len(), 1);
+            assert_eq!(violations[0].path(), dir.join("link"));
+            assert_eq!(violations[0].kind(), ViolationKind::UntrustedOwner);
+            assert_eq!(*violations[0].owner(), *crate::Userid::from_raw_uid(&65534));
+        }
+    }
+
+    #[test]
+    fn test_ownership_check() {
+        let dir = TempDir::new("ownership");
+
+        let owner = crate::Userid::from_raw_uid(&4000123);
+        let root = crate::Userid::from_raw_uid(&0);
+        let euid = effective_uid();
+        let verdict = OwnershipCheck::new().check(dir.path()).unwrap();
+
+        assert_eq!(verdict.trust(), Some(OwnerTrust::EffectiveUser));
+        assert_eq!(*verdict.owner(), *euid);
+
+        symlink("/", dir.join("link")).unwrap();
+        let verdict = OwnershipCheck::new().check(dir.join("link")).unwrap();
+
+        assert_eq!(*verdict.owner(), *euid);
+
+        if *euid == *root {
+            set_owner_nofollow(dir.join("link"), Some(owner), None).unwrap();
+
+            assert!(!OwnershipCheck::new()
+                .check(dir.join("link"))
+                .unwrap()
+                .is_trusted());
+
+            set_owner_nofollow(dir.path(), Some(owner), None).unwrap();
+            let mut check = OwnershipCheck::new();
+
+            assert!(!check.check_as(dir.path(), root, None).unwrap().is_trusted());
+            assert_eq!(
+                check
+                    .check_as(dir.path(), root, Some(owner))
+                    .unwrap()
+                    .trust(),
+                Some(OwnerTrust::SudoUser)
+            );
+            assert!(!check
+                .honor_sudo(false)
+                .check_as(dir.path(), root, Some(owner))
+                .unwrap()
+                .is_trusted());
+            assert_eq!(
+                check
+                    .allow(owner)
+                    .check_as(dir.path(), root, None)
+                    .unwrap()
+                    .trust(),
+                Some(OwnerTrust::AllowList)
+            );
+        }
+    }
+}
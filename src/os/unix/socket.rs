@@ -0,0 +1,359 @@
+use std::io;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::ptr;
+
+use crate::os::unix::{GroupidBufExt, GroupidExt, UseridBufExt, UseridExt};
+
+/// The credentials of a process on the other end of a Unix socket.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pid: libc::pid_t,
+    uid: crate::UseridBuf,
+    gid: crate::GroupidBuf,
+}
+
+impl PeerCredentials {
+    /// Creates a new `PeerCredentials` instance.
+    pub fn new(pid: libc::pid_t, uid: &crate::Userid, gid: &crate::Groupid) -> Self {
+        Self::from_ucred(libc::ucred {
+            pid,
+            uid: uid.as_raw_uid(),
+            gid: gid.as_raw_gid(),
+        })
+    }
+
+    /// Creates a new `PeerCredentials` instance holding the process id, effective
+    /// user id and effective group id of the calling process.
+    pub fn current() -> Self {
+        Self::from_ucred(unsafe {
+            libc::ucred {
+                pid: libc::getpid(),
+                uid: libc::geteuid(),
+                gid: libc::getegid(),
+            }
+        })
+    }
+
+    fn from_ucred(ucred: libc::ucred) -> Self {
+        Self {
+            pid: ucred.pid,
+            uid: crate::UseridBuf::from_raw_uid(ucred.uid),
+            gid: crate::GroupidBuf::from_raw_gid(ucred.gid),
+        }
+    }
+
+    fn to_ucred(&self) -> libc::ucred {
+        libc::ucred {
+            pid: self.pid,
+            uid: self.uid.as_raw_uid(),
+            gid: self.gid.as_raw_gid(),
+        }
+    }
+
+    /// Returns the process id of the peer.
+    #[inline]
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// Returns the user id of the peer.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Returns the group id of the peer.
+    #[inline]
+    pub fn gid(&self) -> &crate::Groupid {
+        &self.gid
+    }
+}
+
+/// Returns the credentials the peer of stream had when it called `connect(2)`
+/// or `socketpair(2)`, as checked by the kernel.
+///
+/// # libc functions used
+///
+/// - [`getsockopt`](https://man7.org/linux/man-pages/man2/getsockopt.2.html) with `SO_PEERCRED`
+pub fn peer_credentials(stream: &UnixStream) -> Result<PeerCredentials, io::Error> {
+    let mut ucred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let return_code = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut ucred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if return_code == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(PeerCredentials::from_ucred(ucred))
+    }
+}
+
+/// Returns the supplementary groups the peer of stream had when it called
+/// `connect(2)` or `socketpair(2)`.
+///
+/// Requires Linux 4.13 or later.
+///
+/// # libc functions used
+///
+/// - [`getsockopt`](https://man7.org/linux/man-pages/man2/getsockopt.2.html) with `SO_PEERGROUPS`
+#[cfg(target_os = "linux")]
+pub fn peer_groups(stream: &UnixStream) -> Result<Vec<crate::GroupidBuf>, io::Error> {
+    let mut groups: Vec<libc::gid_t> = vec![0; 32];
+
+    loop {
+        let mut len = (groups.len() * mem::size_of::<libc::gid_t>()) as libc::socklen_t;
+        let return_code = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERGROUPS,
+                groups.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+            )
+        };
+
+        if return_code != -1 {
+            groups.truncate(len as usize / mem::size_of::<libc::gid_t>());
+            return Ok(groups
+                .into_iter()
+                .map(crate::GroupidBuf::from_raw_gid)
+                .collect());
+        }
+
+        // If groups is too small, len is set to the size needed
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+        groups.resize(len as usize / mem::size_of::<libc::gid_t>(), 0);
+    }
+}
+
+/// Sets whether `SCM_CREDENTIALS` messages are received on stream, which must
+/// be enabled before the peer sends the data they come with.
+///
+/// # libc functions used
+///
+/// - [`setsockopt`](https://man7.org/linux/man-pages/man2/setsockopt.2.html) with `SO_PASSCRED`
+pub fn set_pass_credentials(stream: &UnixStream, pass_credentials: bool) -> Result<(), io::Error> {
+    let value = pass_credentials as libc::c_int;
+    let return_code = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if return_code == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Sends data on stream with an `SCM_CREDENTIALS` message holding credentials,
+/// and returns the number of bytes sent.
+///
+/// The kernel rejects credentials other than those of the calling process,
+/// unless it has `CAP_SYS_ADMIN`, `CAP_SETUID` and `CAP_SETGID` respectively.
+/// data must not be empty.
+///
+/// # libc functions used
+///
+/// - [`sendmsg`](https://man7.org/linux/man-pages/man2/sendmsg.2.html)
+pub fn send_credentials(
+    stream: &UnixStream,
+    credentials: &PeerCredentials,
+    data: &[u8],
+) -> Result<usize, io::Error> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut control = control_buffer();
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control_len() as _;
+
+    // SAFETY: control is large enough for a single ucred message
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_CREDENTIALS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::ucred>() as u32) as _;
+        ptr::write_unaligned(
+            libc::CMSG_DATA(cmsg) as *mut libc::ucred,
+            credentials.to_ucred(),
+        );
+    }
+
+    let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) };
+    if sent == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(sent as usize)
+    }
+}
+
+/// Receives data from stream into buf, and returns the number of bytes received
+/// with the credentials of the sender, if an `SCM_CREDENTIALS` message came with them.
+///
+/// Credentials are only received once enabled with [`set_pass_credentials`].
+/// The kernel attaches the credentials of the sender if it sent none.
+///
+/// Returns an [`io::ErrorKind::InvalidData`] error if the sender attached more
+/// control messages than fit alongside the credentials, such as file
+/// descriptors, in which case the data is consumed and the messages that did
+/// not fit are discarded by the kernel.
+///
+/// # libc functions used
+///
+/// - [`recvmsg`](https://man7.org/linux/man-pages/man2/recvmsg.2.html)
+pub fn recv_credentials(
+    stream: &UnixStream,
+    buf: &mut [u8],
+) -> Result<(usize, Option<PeerCredentials>), io::Error> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = control_buffer();
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control_len() as _;
+
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if received == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "control messages truncated",
+        ));
+    }
+
+    let mut credentials = None;
+    // SAFETY: the kernel filled control with well-formed messages up to msg_controllen
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_CREDENTIALS
+            {
+                let ucred = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::ucred);
+                credentials = Some(PeerCredentials::from_ucred(ucred));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    Ok((received as usize, credentials))
+}
+
+/// Returns the size of a control buffer holding a single ucred message.
+fn control_len() -> usize {
+    unsafe { libc::CMSG_SPACE(mem::size_of::<libc::ucred>() as u32) as usize }
+}
+
+/// Returns a zeroed control buffer of at least `control_len()` bytes, aligned
+/// for `cmsghdr`.
+fn control_buffer() -> Vec<u64> {
+    vec![0; (control_len() + 7) / 8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::unix::privilege::get_groups;
+
+    #[test]
+    fn test_peer_credentials() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let credentials = peer_credentials(&a).unwrap();
+
+        assert_eq!(credentials, PeerCredentials::current());
+        assert_eq!(peer_credentials(&b).unwrap().pid(), unsafe {
+            libc::getpid()
+        });
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_peer_groups() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let groups = peer_groups(&a).unwrap();
+        let mut expected = get_groups().unwrap();
+        expected.sort_unstable();
+        let mut groups: Vec<libc::gid_t> = groups.iter().map(|gid| gid.as_raw_gid()).collect();
+        groups.sort_unstable();
+
+        assert_eq!(groups, expected);
+    }
+
+    #[test]
+    fn test_send_recv_credentials() {
+        let (a, b) = UnixStream::pair().unwrap();
+        set_pass_credentials(&b, true).unwrap();
+
+        let sent = send_credentials(&a, &PeerCredentials::current(), b"hello").unwrap();
+        let mut buf = [0; 16];
+        let (received, credentials) = recv_credentials(&b, &mut buf).unwrap();
+
+        assert_eq!(sent, 5);
+        assert_eq!(&buf[..received], b"hello");
+        assert_eq!(credentials, Some(PeerCredentials::current()));
+    }
+
+    #[test]
+    fn test_recv_credentials_truncated() {
+        let (a, b) = UnixStream::pair().unwrap();
+        set_pass_credentials(&b, true).unwrap();
+
+        // Sends a file descriptor, which does not fit alongside the credentials
+        let fd = a.as_raw_fd();
+        let mut data = *b"x";
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        let mut control = control_buffer();
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(mem::size_of_val(&fd) as u32) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of_val(&fd) as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, fd);
+        }
+        assert_eq!(unsafe { libc::sendmsg(a.as_raw_fd(), &msg, 0) }, 1);
+
+        let mut buf = [0; 16];
+        let err = recv_credentials(&b, &mut buf).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,48 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory created in the temporary directory for a test, removed with its
+/// contents when dropped, so that a failing assertion does not leak it.
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new empty directory named after name, the process id and a
+    /// counter, so that tests running in parallel never share one.
+    pub(crate) fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "user_utils-{}-{}-{}",
+            name,
+            unsafe { libc::getpid() },
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left over by an earlier process with the same pid
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    /// Returns the path of the directory.
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of name in the directory.
+    #[inline]
+    pub(crate) fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::ops;
+use std::path::Path;
+
+use crate::os::unix::fs::{walk_tree_at, OwnerNameCache};
+use crate::os::unix::{GroupidBufExt, UseridBufExt};
+use crate::Error;
+
+/// The disk space and inodes used by files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    apparent_bytes: u64,
+    allocated_bytes: u64,
+    inodes: u64,
+}
+
+impl Usage {
+    /// Returns the total size of the files, as reported by `ls -l`.
+    #[inline]
+    pub fn apparent_bytes(&self) -> u64 {
+        self.apparent_bytes
+    }
+
+    /// Returns the total size of the blocks allocated to the files, as reported by `du`.
+    #[inline]
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Returns the number of inodes used by the files.
+    #[inline]
+    pub fn inodes(&self) -> u64 {
+        self.inodes
+    }
+}
+
+impl ops::AddAssign for Usage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.apparent_bytes += rhs.apparent_bytes;
+        self.allocated_bytes += rhs.allocated_bytes;
+        self.inodes += rhs.inodes;
+    }
+}
+
+/// A walker totalling the disk usage of a tree per owning user id and group id,
+/// for file systems without quota support.
+#[derive(Debug, Clone, Default)]
+pub struct UsageScanner {
+    one_file_system: bool,
+}
+
+impl UsageScanner {
+    /// Creates a new `UsageScanner` instance crossing file systems.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether directories on other file systems are not descended into.
+    pub fn one_file_system(&mut self, one_file_system: bool) -> &mut Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Totals the disk usage of the tree rooted at path, without following
+    /// symbolic links.
+    ///
+    /// Files with multiple hard links are counted once. The names of the
+    /// owners are searched in the user and group database once per id.
+    pub fn scan<P: AsRef<Path>>(&self, path: P) -> Result<UsageReport, Error> {
+        let mut total = Usage::default();
+        let mut users: BTreeMap<libc::uid_t, Usage> = BTreeMap::new();
+        let mut groups: BTreeMap<libc::gid_t, Usage> = BTreeMap::new();
+        let mut seen_inodes = HashSet::new();
+
+        walk_tree_at(path.as_ref(), self.one_file_system, &mut |entry| {
+            if !entry.is_dir() && entry.nlink() > 1 && !seen_inodes.insert(entry.dev_ino()) {
+                return Ok(());
+            }
+
+            let usage = Usage {
+                apparent_bytes: entry.size(),
+                // st_blocks is always in units of 512 bytes
+                allocated_bytes: entry.blocks() * 512,
+                inodes: 1,
+            };
+            total += usage;
+            *users.entry(entry.uid()).or_default() += usage;
+            *groups.entry(entry.gid()).or_default() += usage;
+
+            Ok(())
+        })?;
+
+        let mut names = OwnerNameCache::default();
+        let users = users
+            .into_iter()
+            .map(|(uid, usage)| {
+                Ok(UserUsage {
+                    uid: crate::UseridBuf::from_raw_uid(uid),
+                    name: names.user_name(uid)?.map(OsStr::to_os_string),
+                    usage,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let groups = groups
+            .into_iter()
+            .map(|(gid, usage)| {
+                Ok(GroupUsage {
+                    gid: crate::GroupidBuf::from_raw_gid(gid),
+                    name: names.group_name(gid)?.map(OsStr::to_os_string),
+                    usage,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(UsageReport {
+            total,
+            users,
+            groups,
+        })
+    }
+}
+
+/// The disk usage of the files owned by a user id.
+#[derive(Debug)]
+pub struct UserUsage {
+    uid: crate::UseridBuf,
+    name: Option<OsString>,
+    usage: Usage,
+}
+
+impl UserUsage {
+    /// Returns the user id owning the files.
+    #[inline]
+    pub fn uid(&self) -> &crate::Userid {
+        &self.uid
+    }
+
+    /// Returns the login name of the user, or `None` if the user id has no record.
+    #[inline]
+    pub fn name(&self) -> Option<&OsStr> {
+        self.name.as_deref()
+    }
+
+    /// Returns the disk usage of the files.
+    #[inline]
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+}
+
+/// The disk usage of the files owned by a group id.
+#[derive(Debug)]
+pub struct GroupUsage {
+    gid: crate::GroupidBuf,
+    name: Option<OsString>,
+    usage: Usage,
+}
+
+impl GroupUsage {
+    /// Returns the group id owning the files.
+    #[inline]
+    pub fn gid(&self) -> &crate::Groupid {
+        &self.gid
+    }
+
+    /// Returns the name of the group, or `None` if the group id has no record.
+    #[inline]
+    pub fn name(&self) -> Option<&OsStr> {
+        self.name.as_deref()
+    }
+
+    /// Returns the disk usage of the files.
+    #[inline]
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+}
+
+/// The result of [`UsageScanner::scan`].
+#[derive(Debug)]
+pub struct UsageReport {
+    total: Usage,
+    users: Vec<UserUsage>,
+    groups: Vec<GroupUsage>,
+}
+
+impl UsageReport {
+    /// Returns the disk usage of the whole tree.
+    #[inline]
+    pub fn total(&self) -> Usage {
+        self.total
+    }
+
+    /// Returns the disk usage per user id, in ascending order of user id.
+    #[inline]
+    pub fn users(&self) -> &[UserUsage] {
+        &self.users
+    }
+
+    /// Returns the disk usage per group id, in ascending order of group id.
+    #[inline]
+    pub fn groups(&self) -> &[GroupUsage] {
+        &self.groups
+    }
+
+    /// Returns the disk usage of the files owned by uid.
+    pub fn user(&self, uid: &crate::Userid) -> Option<Usage> {
+        self.users
+            .iter()
+            .find(|user| *user.uid() == *uid)
+            .map(UserUsage::usage)
+    }
+
+    /// Returns the disk usage of the files owned by gid.
+    pub fn group(&self, gid: &crate::Groupid) -> Option<Usage> {
+        self.groups
+            .iter()
+            .find(|group| *group.gid() == *gid)
+            .map(GroupUsage::usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::os::unix::test_util::TempDir;
+    use crate::os::unix::{set_owner_nofollow, UseridExt};
+
+    #[test]
+    fn test_scan_usage() {
+        let dir = TempDir::new("usage");
+        fs::write(dir.join("file"), [0; 1000]).unwrap();
+        fs::hard_link(dir.join("file"), dir.join("link")).unwrap();
+
+        let euid = unsafe { libc::geteuid() };
+        let report = UsageScanner::new().scan(dir.path()).unwrap();
+        let usage = report.user(crate::Userid::from_raw_uid(&euid)).unwrap();
+
+        assert_eq!(report.total().inodes(), 2);
+        assert_eq!(usage.inodes(), 2);
+        assert!(usage.apparent_bytes() >= 1000);
+        assert_eq!(report.users().len(), 1);
+
+        if euid == 0 {
+            set_owner_nofollow(
+                dir.join("file"),
+                Some(crate::Userid::from_raw_uid(&65534)),
+                None,
+            )
+            .unwrap();
+
+            let report = UsageScanner::new().scan(dir.path()).unwrap();
+            let nobody = &report.users()[1];
+
+            assert_eq!(nobody.usage().apparent_bytes(), 1000);
+            assert_eq!(nobody.usage().inodes(), 1);
+            assert_eq!(nobody.name(), Some(OsStr::new("nobody")));
+        }
+    }
+}
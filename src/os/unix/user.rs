@@ -1,4 +1,4 @@
-use std::ffi::{c_char, CStr, OsStr, OsString};
+use std::ffi::{c_char, CStr, CString, OsStr, OsString};
 use std::fmt;
 use std::io;
 use std::mem;
@@ -26,6 +26,14 @@ pub trait UseridExt: private::Sealed {
     fn lookup_passwd(&self) -> Result<Passwd, Error>;
 }
 
+/// Unix-specific extensions to [`UseridBuf`](crate::UseridBuf).
+pub trait UseridBufExt: private::Sealed {
+    /// Creates a new `UseridBuf` instance holding the given raw uid.
+    fn from_raw_uid(uid: libc::uid_t) -> Self
+    where
+        Self: Sized;
+}
+
 #[derive(PartialEq, Eq)]
 pub(crate) struct Userid {
     raw_uid: libc::uid_t,
@@ -110,6 +118,13 @@ impl ops::Deref for UseridBuf {
     }
 }
 
+impl private::Sealed for UseridBuf {}
+impl UseridBufExt for UseridBuf {
+    fn from_raw_uid(uid: libc::uid_t) -> Self {
+        Self { raw_uid: uid }
+    }
+}
+
 /// Metadata information about a user.
 ///
 /// Newtype pattern around [`passwd`](https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/pwd.h.html)
@@ -224,6 +239,106 @@ pub fn get_pw_by_uid(uid: libc::uid_t) -> Result<Passwd, Error> {
     }
 }
 
+/// Searches user database and returns the passwd record of login name.
+///
+/// # libc functions used
+///
+/// - [`getpwnam_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getpwnam_r.html)
+pub fn get_pw_by_name(name: &OsStr) -> Result<Passwd, Error> {
+    // A login name containing a nul byte cannot be in user database
+    let name = match CString::new(name.as_bytes()) {
+        Ok(name) => name,
+        Err(_) => return Err(Error::NoRecord),
+    };
+
+    let mut buflen = unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) };
+    if buflen == -1 {
+        buflen = 1024;
+    }
+
+    let mut passwd = Passwd {
+        raw_pwd: unsafe { mem::zeroed() },
+        buf: vec![0; buflen as usize],
+    };
+    let mut result: *mut libc::passwd = ptr::null_mut();
+
+    unsafe {
+        let return_code = libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd.raw_pwd,
+            passwd.buf.as_mut_ptr(),
+            buflen as usize,
+            &mut result,
+        );
+
+        // On success, return_code is 0
+        if return_code == 0 {
+            // If passwd record is found for name, result is a pointer to pwd
+            if result == &mut passwd.raw_pwd {
+                Ok(passwd)
+            } else {
+                Err(Error::NoRecord)
+            }
+        } else {
+            Err(Error::last_os_error())
+        }
+    }
+}
+
+/// Returns the effective user id of the calling process.
+///
+/// # libc functions used
+///
+/// - [`geteuid`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/geteuid.html)
+pub fn effective_uid() -> crate::UseridBuf {
+    crate::UseridBuf::from_raw_uid(unsafe { libc::geteuid() })
+}
+
+/// Returns the login name of the user logged in on the controlling terminal
+/// of the calling process.
+///
+/// Returns [`Error::NoRecord`] if the calling process has no controlling
+/// terminal or no login session is recorded for it.
+///
+/// # libc functions used
+///
+/// - [`getlogin_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getlogin_r.html)
+pub fn get_login() -> Result<OsString, Error> {
+    let mut buflen = unsafe { libc::sysconf(libc::_SC_LOGIN_NAME_MAX) };
+    if buflen == -1 {
+        buflen = 256;
+    }
+    let mut buf: Vec<c_char> = vec![0; buflen as usize];
+
+    loop {
+        // On failure, return_code is an error number
+        match unsafe { getlogin_r(buf.as_mut_ptr(), buf.len()) } {
+            0 => break,
+            libc::ERANGE if buf.len() < MAX_LOGIN_BUF_LEN => buf.resize(buf.len() * 2, 0),
+            libc::ERANGE => {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "getlogin_r keeps reporting a longer login name",
+                )))
+            }
+            libc::ENOTTY | libc::ENXIO | libc::ENOENT => return Err(Error::NoRecord),
+            return_code => return Err(Error::Io(io::Error::from_raw_os_error(return_code))),
+        }
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+
+    Ok(OsString::from_vec(name.to_bytes().to_vec()))
+}
+
+/// The size past which the buffer for `getlogin_r` stops growing, far above
+/// the 32 bytes `utmp(5)` holds for a name.
+const MAX_LOGIN_BUF_LEN: usize = 64 * 1024;
+
+// Not exposed by the libc crate
+extern "C" {
+    fn getlogin_r(buf: *mut c_char, bufsize: libc::size_t) -> libc::c_int;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -284,4 +399,29 @@ mod tests {
 
         assert!(matches!(result, Err(Error::NoRecord)));
     }
+
+    #[test]
+    fn test_get_pw_by_name_ok() {
+        let pwd = get_pw_by_uid(unsafe { libc::getuid() }).unwrap();
+        let result = get_pw_by_name(pwd.name()).unwrap();
+
+        assert_eq!(result.uid(), pwd.uid());
+    }
+
+    #[test]
+    fn test_get_pw_by_name_norecord() {
+        let result = get_pw_by_name(OsStr::new("user_utils-no-such-user"));
+
+        assert!(matches!(result, Err(Error::NoRecord)));
+    }
+
+    #[test]
+    fn test_get_login() {
+        // Test runners usually have no controlling terminal or login session
+        match get_login() {
+            Ok(name) => assert!(!name.is_empty()),
+            Err(Error::NoRecord) => (),
+            Err(err) => panic!("{:?}", err),
+        }
+    }
 }
@@ -0,0 +1,371 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::os::unix::{GroupidExt, IdMap, IdMapRange, Passwd, UseridExt};
+
+/// The maximum number of lines accepted by the kernel in an id map since Linux 4.15.
+const MAX_ID_MAP_RANGES: usize = 340;
+
+/// A range of subordinate ids allocated to a user in `/etc/subuid` or `/etc/subgid`.
+///
+/// See [`subuid(5)`](https://man7.org/linux/man-pages/man5/subuid.5.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubidRange {
+    start: u32,
+    count: u32,
+}
+
+impl SubidRange {
+    /// Creates a new `SubidRange` instance.
+    #[inline]
+    pub fn new(start: u32, count: u32) -> Self {
+        Self { start, count }
+    }
+
+    /// Returns the first subordinate id of range.
+    #[inline]
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Returns the number of subordinate ids in range.
+    #[inline]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+/// The value written to `/proc/<pid>/setgroups`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetgroupsPolicy {
+    /// Allows `setgroups(2)` in the user namespace.
+    Allow,
+
+    /// Denies `setgroups(2)` in the user namespace.
+    ///
+    /// Required before an unprivileged process can write a gid map.
+    Deny,
+}
+
+impl SetgroupsPolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+/// The id maps and setgroups policy of a user namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserNamespaceMaps {
+    uid_map: IdMap,
+    gid_map: IdMap,
+    setgroups: SetgroupsPolicy,
+}
+
+impl UserNamespaceMaps {
+    /// Creates a new `UserNamespaceMaps` instance.
+    pub fn new(uid_map: IdMap, gid_map: IdMap, setgroups: SetgroupsPolicy) -> Self {
+        Self {
+            uid_map,
+            gid_map,
+            setgroups,
+        }
+    }
+
+    /// Creates the maps an unprivileged process can write without help from
+    /// `newuidmap(1)`: its own user id and group id mapped to id 0, with setgroups denied.
+    pub fn single(userid: &crate::Userid, groupid: &crate::Groupid) -> Self {
+        Self::new(
+            IdMap::from_ranges(vec![IdMapRange::new(0, userid.as_raw_uid(), 1)]),
+            IdMap::from_ranges(vec![IdMapRange::new(0, groupid.as_raw_gid(), 1)]),
+            SetgroupsPolicy::Deny,
+        )
+    }
+
+    /// Returns the uid map.
+    #[inline]
+    pub fn uid_map(&self) -> &IdMap {
+        &self.uid_map
+    }
+
+    /// Returns the gid map.
+    #[inline]
+    pub fn gid_map(&self) -> &IdMap {
+        &self.gid_map
+    }
+
+    /// Returns the setgroups policy.
+    #[inline]
+    pub fn setgroups(&self) -> SetgroupsPolicy {
+        self.setgroups
+    }
+
+    /// Writes the setgroups policy, uid map and gid map of the process with the given pid.
+    ///
+    /// Both maps are validated before anything is written. The setgroups policy
+    /// is written first, as the kernel requires it before the gid map.
+    pub fn apply(&self, pid: libc::pid_t) -> Result<(), io::Error> {
+        validate_id_map(&self.uid_map)?;
+        validate_id_map(&self.gid_map)?;
+
+        write_setgroups(pid, self.setgroups)?;
+        write_id_map(&format!("/proc/{}/uid_map", pid), &self.uid_map)?;
+        write_id_map(&format!("/proc/{}/gid_map", pid), &self.gid_map)
+    }
+}
+
+/// Creates an id map with id 0 mapped to `id` and the ids from 1 onwards
+/// mapped to the given subordinate id ranges in order.
+pub fn subordinate_id_map(id: u32, subids: &[SubidRange]) -> IdMap {
+    let mut ranges = vec![IdMapRange::new(0, id, 1)];
+    let mut inside: u32 = 1;
+
+    for subid in subids {
+        let count = subid.count.min(u32::MAX - inside);
+        if count == 0 {
+            break;
+        }
+
+        ranges.push(IdMapRange::new(inside, subid.start, count));
+        inside += count;
+    }
+
+    IdMap::from_ranges(ranges)
+}
+
+/// Returns the subordinate uid ranges allocated to user in `/etc/subuid`.
+///
+/// As with `newuidmap(1)`, entries are matched against the login name and uid of user.
+pub fn get_subuid_ranges(pwd: &Passwd) -> Result<Vec<SubidRange>, io::Error> {
+    let contents = fs::read("/etc/subuid")?;
+
+    Ok(parse_subid_ranges(
+        &contents,
+        pwd.name(),
+        pwd.uid().as_raw_uid(),
+    ))
+}
+
+/// Returns the subordinate gid ranges allocated to user in `/etc/subgid`.
+///
+/// As with `newgidmap(1)`, entries are matched against the login name and uid of user.
+pub fn get_subgid_ranges(pwd: &Passwd) -> Result<Vec<SubidRange>, io::Error> {
+    let contents = fs::read("/etc/subgid")?;
+
+    Ok(parse_subid_ranges(
+        &contents,
+        pwd.name(),
+        pwd.uid().as_raw_uid(),
+    ))
+}
+
+/// Writes the setgroups policy of the process with the given pid.
+pub fn write_setgroups(pid: libc::pid_t, policy: SetgroupsPolicy) -> Result<(), io::Error> {
+    match fs::write(format!("/proc/{}/setgroups", pid), policy.as_str()) {
+        // Kernels before 3.19 do not have /proc/<pid>/setgroups
+        Err(err) if err.kind() == io::ErrorKind::NotFound && policy == SetgroupsPolicy::Deny => {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Writes the uid map of the process with the given pid.
+pub fn write_uid_map(pid: libc::pid_t, map: &IdMap) -> Result<(), io::Error> {
+    validate_id_map(map)?;
+    write_id_map(&format!("/proc/{}/uid_map", pid), map)
+}
+
+/// Writes the gid map of the process with the given pid.
+pub fn write_gid_map(pid: libc::pid_t, map: &IdMap) -> Result<(), io::Error> {
+    validate_id_map(map)?;
+    write_id_map(&format!("/proc/{}/gid_map", pid), map)
+}
+
+fn write_id_map(path: &str, map: &IdMap) -> Result<(), io::Error> {
+    // The kernel requires the whole map to be written in a single write(2)
+    let contents = map.to_string();
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| io::Write::write(&mut &file, contents.as_bytes()))?;
+
+    if written == contents.len() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "id map was not written in a single write",
+        ))
+    }
+}
+
+/// Checks id map against the rules enforced by the kernel, so that an invalid
+/// map is reported before any file under `/proc/<pid>` is written.
+fn validate_id_map(map: &IdMap) -> Result<(), io::Error> {
+    let invalid = |msg: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    let ranges = map.ranges();
+
+    if ranges.is_empty() {
+        return invalid("id map is empty");
+    }
+    if ranges.len() > MAX_ID_MAP_RANGES {
+        return invalid("id map has too many ranges");
+    }
+
+    for (i, range) in ranges.iter().enumerate() {
+        if range.count() == 0
+            || range.inside().checked_add(range.count()).is_none()
+            || range.outside().checked_add(range.count()).is_none()
+        {
+            return invalid("id map range is empty or overflows");
+        }
+
+        for other in &ranges[..i] {
+            if overlaps(range.inside(), other.inside(), range.count(), other.count())
+                || overlaps(
+                    range.outside(),
+                    other.outside(),
+                    range.count(),
+                    other.count(),
+                )
+            {
+                return invalid("id map ranges overlap");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn overlaps(start_1: u32, start_2: u32, count_1: u32, count_2: u32) -> bool {
+    start_1 < start_2 + count_2 && start_2 < start_1 + count_1
+}
+
+fn parse_subid_ranges(contents: &[u8], name: &OsStr, id: u32) -> Vec<SubidRange> {
+    let id = id.to_string();
+
+    contents
+        .split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let mut fields = line.split(|&b| b == b':');
+            let owner = fields.next()?;
+            let start = std::str::from_utf8(fields.next()?)
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+            let count = std::str::from_utf8(fields.next()?)
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+
+            if owner == name.as_bytes() || owner == id.as_bytes() {
+                Some(SubidRange::new(start, count))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::fd::RawFd;
+
+    #[test]
+    fn test_parse_subid_ranges() {
+        let contents = b"alice:100000:65536\n1000:200000:10\nbob:300000:65536\nbad line\n";
+        let ranges = parse_subid_ranges(contents, OsStr::new("alice"), 1000);
+
+        assert_eq!(
+            ranges,
+            [SubidRange::new(100000, 65536), SubidRange::new(200000, 10)]
+        );
+    }
+
+    #[test]
+    fn test_subordinate_id_map() {
+        let map = subordinate_id_map(1000, &[SubidRange::new(100000, 65536)]);
+
+        assert_eq!(map.to_outside(0), Some(1000));
+        assert_eq!(map.to_outside(1), Some(100000));
+        assert_eq!(map.to_outside(65536), Some(165535));
+        assert!(validate_id_map(&map).is_ok());
+    }
+
+    #[test]
+    fn test_validate_id_map_overlap() {
+        let map = IdMap::from_ranges(vec![IdMapRange::new(0, 1000, 10), IdMapRange::new(5, 0, 1)]);
+
+        assert!(validate_id_map(&map).is_err());
+        assert!(validate_id_map(&IdMap::from_ranges(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_apply_single_to_child() {
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        let mut ready: [RawFd; 2] = [0; 2];
+        let mut mapped: [RawFd; 2] = [0; 2];
+
+        unsafe {
+            assert_eq!(libc::pipe(ready.as_mut_ptr()), 0);
+            assert_eq!(libc::pipe(mapped.as_mut_ptr()), 0);
+        }
+
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            // Child: enter a new user namespace, then wait for the parent to map it
+            unsafe {
+                let mut byte = 0u8;
+                libc::close(ready[0]);
+                libc::close(mapped[1]);
+                let status = libc::unshare(libc::CLONE_NEWUSER);
+                libc::write(ready[1], &status as *const i32 as *const _, 4);
+                libc::read(mapped[0], &mut byte as *mut u8 as *mut _, 1);
+
+                let ok = libc::getuid() == 0 && libc::getgid() == 0;
+                libc::_exit(if ok { 0 } else { 1 });
+            }
+        }
+        unsafe {
+            libc::close(ready[1]);
+            libc::close(mapped[0]);
+        }
+
+        let mut status: i32 = -1;
+        unsafe { libc::read(ready[0], &mut status as *mut i32 as *mut _, 4) };
+
+        let result = if status == 0 {
+            UserNamespaceMaps::single(
+                crate::Userid::from_raw_uid(&uid),
+                crate::Groupid::from_raw_gid(&gid),
+            )
+            .apply(pid)
+        } else {
+            Ok(())
+        };
+
+        // Closing the pipe wakes the child up whether the maps were written or not
+        let mut wstatus = 0;
+        unsafe {
+            libc::close(ready[0]);
+            libc::close(mapped[1]);
+            libc::waitpid(pid, &mut wstatus, 0);
+        }
+        result.unwrap();
+
+        // User namespaces may be disabled on the host
+        if status == 0 {
+            assert!(libc::WIFEXITED(wstatus) && libc::WEXITSTATUS(wstatus) == 0);
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::os::unix::{get_pw_by_name, Passwd};
+use crate::Error;
+
+/// The path of the database of users currently logged in.
+pub const UTMP_PATH: &str = "/var/run/utmp";
+
+/// The path of the history of logins and logouts.
+pub const WTMP_PATH: &str = "/var/log/wtmp";
+
+/// The path of the history of failed login attempts.
+pub const BTMP_PATH: &str = "/var/log/btmp";
+
+/// The size of a `struct utmp` record.
+///
+/// glibc keeps `ut_session` and `ut_tv` 32-bit on 32-bit targets and on the
+/// 64-bit targets defining `__WORDSIZE_TIME64_COMPAT32`, such as x86_64, so
+/// that 32-bit programs can share the files. It makes them `long` and
+/// `struct timeval` on aarch64, s390x and loongarch64, as do musl and bionic
+/// on every 64-bit target.
+const UTMP_SIZE: usize = if WIDE_UTMP { 400 } else { 384 };
+
+/// Whether `ut_session` and `ut_tv` are `long` and `struct timeval`, and the
+/// `ll_time` of `struct lastlog` is a `time_t`.
+pub(crate) const WIDE_UTMP: bool = cfg!(any(
+    all(
+        target_env = "gnu",
+        any(
+            target_arch = "aarch64",
+            target_arch = "s390x",
+            target_arch = "loongarch64"
+        )
+    ),
+    all(not(target_env = "gnu"), target_pointer_width = "64")
+));
+
+/// The size of `ut_session`, `ut_tv.tv_sec` and `ut_tv.tv_usec`.
+const UT_WORD: usize = if WIDE_UTMP { 8 } else { 4 };
+
+// Offsets of the fields of struct utmp from <bits/utmp.h>
+const UT_TYPE: usize = 0;
+const UT_PID: usize = 4;
+const UT_LINE: (usize, usize) = (8, 32);
+const UT_ID: (usize, usize) = (40, 4);
+const UT_USER: (usize, usize) = (44, 32);
+const UT_HOST: (usize, usize) = (76, 256);
+const UT_EXIT: usize = 332;
+const UT_SESSION: usize = 336;
+const UT_TV: usize = UT_SESSION + UT_WORD;
+const UT_ADDR_V6: usize = UT_TV + 2 * UT_WORD;
+
+/// The type of a utmp record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtmpType {
+    /// A record without valid information.
+    Empty,
+
+    /// A change of the system run level.
+    RunLevel,
+
+    /// The time of system boot.
+    BootTime,
+
+    /// The time after a system clock change.
+    NewTime,
+
+    /// The time before a system clock change.
+    OldTime,
+
+    /// A process spawned by init.
+    InitProcess,
+
+    /// The session leader of a logged in user, before authentication.
+    LoginProcess,
+
+    /// A logged in user.
+    UserProcess,
+
+    /// A terminated process, such as a logged out user.
+    DeadProcess,
+
+    /// Unused.
+    Accounting,
+
+    /// A type unknown to this library.
+    Unknown(i16),
+}
+
+impl UtmpType {
+    fn from_raw(ut_type: i16) -> Self {
+        match ut_type {
+            0 => Self::Empty,
+            1 => Self::RunLevel,
+            2 => Self::BootTime,
+            3 => Self::NewTime,
+            4 => Self::OldTime,
+            5 => Self::InitProcess,
+            6 => Self::LoginProcess,
+            7 => Self::UserProcess,
+            8 => Self::DeadProcess,
+            9 => Self::Accounting,
+            ut_type => Self::Unknown(ut_type),
+        }
+    }
+}
+
+/// A record of a `utmp(5)` file, such as `/var/run/utmp`, `/var/log/wtmp`
+/// or `/var/log/btmp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtmpEntry {
+    kind: UtmpType,
+    pid: libc::pid_t,
+    line: OsString,
+    id: OsString,
+    user: OsString,
+    host: OsString,
+    exit_status: (i16, i16),
+    session: i32,
+    time: SystemTime,
+    addr: Option<IpAddr>,
+}
+
+impl UtmpEntry {
+    /// Decodes a record, which must be exactly the size of a `struct utmp` and
+    /// hold a time representable as a [`SystemTime`].
+    pub fn from_bytes(record: &[u8]) -> Option<Self> {
+        if record.len() != UTMP_SIZE {
+            return None;
+        }
+
+        let i16_at = |offset: usize| i16::from_ne_bytes([record[offset], record[offset + 1]]);
+        let i32_at =
+            |offset: usize| i32::from_ne_bytes(record[offset..offset + 4].try_into().unwrap());
+        let string_at = |(offset, len): (usize, usize)| {
+            let field = &record[offset..offset + len];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+            OsString::from_vec(field[..end].to_vec())
+        };
+
+        // 32-bit times are read as unsigned to reach past 2038
+        let word_at = |offset: usize| {
+            let word = &record[offset..offset + UT_WORD];
+            if WIDE_UTMP {
+                u64::from_ne_bytes(word.try_into().unwrap())
+            } else {
+                u32::from_ne_bytes(word.try_into().unwrap()) as u64
+            }
+        };
+        let time = SystemTime::UNIX_EPOCH
+            .checked_add(Duration::from_secs(word_at(UT_TV)))?
+            .checked_add(Duration::from_micros(word_at(UT_TV + UT_WORD)))?;
+
+        // Addresses are stored in network byte order, IPv4 ones in the first word only
+        let addr: [u8; 16] = record[UT_ADDR_V6..UT_ADDR_V6 + 16].try_into().unwrap();
+        let addr = if addr == [0; 16] {
+            None
+        } else if addr[4..] == [0; 12] {
+            Some(IpAddr::V4(Ipv4Addr::new(
+                addr[0], addr[1], addr[2], addr[3],
+            )))
+        } else {
+            Some(IpAddr::V6(Ipv6Addr::from(addr)))
+        };
+
+        Some(Self {
+            kind: UtmpType::from_raw(i16_at(UT_TYPE)),
+            pid: i32_at(UT_PID),
+            line: string_at(UT_LINE),
+            id: string_at(UT_ID),
+            user: string_at(UT_USER),
+            host: string_at(UT_HOST),
+            exit_status: (i16_at(UT_EXIT), i16_at(UT_EXIT + 2)),
+            session: word_at(UT_SESSION) as i32,
+            time,
+            addr,
+        })
+    }
+
+    /// Returns the type of the record.
+    #[inline]
+    pub fn kind(&self) -> UtmpType {
+        self.kind
+    }
+
+    /// Returns the process id of the login process.
+    #[inline]
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// Returns the terminal name, without the `/dev/` prefix.
+    #[inline]
+    pub fn line(&self) -> &OsStr {
+        &self.line
+    }
+
+    /// Returns the terminal name suffix, or inittab id.
+    #[inline]
+    pub fn id(&self) -> &OsStr {
+        &self.id
+    }
+
+    /// Returns the login name of the user.
+    #[inline]
+    pub fn user(&self) -> &OsStr {
+        &self.user
+    }
+
+    /// Returns the host name of a remote login, or the kernel version of a
+    /// `BootTime` record.
+    #[inline]
+    pub fn host(&self) -> &OsStr {
+        &self.host
+    }
+
+    /// Returns the termination status and exit status of a `DeadProcess` record.
+    #[inline]
+    pub fn exit_status(&self) -> (i16, i16) {
+        self.exit_status
+    }
+
+    /// Returns the session id.
+    #[inline]
+    pub fn session(&self) -> i32 {
+        self.session
+    }
+
+    /// Returns the time the record was made.
+    #[inline]
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// Returns the address of the remote host, if any.
+    #[inline]
+    pub fn addr(&self) -> Option<IpAddr> {
+        self.addr
+    }
+
+    /// Searches user database and returns the passwd record of the login name
+    /// of the record.
+    ///
+    /// # libc functions used
+    ///
+    /// - [`getpwnam_r`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/getpwnam_r.html)
+    pub fn lookup_passwd(&self) -> Result<Passwd, Error> {
+        get_pw_by_name(&self.user)
+    }
+
+    fn is_user_login(&self) -> bool {
+        self.kind == UtmpType::UserProcess && !self.user.is_empty()
+    }
+}
+
+/// An iterator over the records of a `utmp(5)` file.
+///
+/// A record truncated at the end of the file yields an error of kind
+/// [`UnexpectedEof`](io::ErrorKind::UnexpectedEof), and one holding a time out of
+/// the range of [`SystemTime`] yields an error of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData), after which iteration can go on
+/// with the next record.
+#[derive(Debug)]
+pub struct UtmpEntries<R> {
+    reader: R,
+}
+
+impl UtmpEntries<BufReader<File>> {
+    /// Opens the `utmp(5)` file at path.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        File::open(path).map(|file| Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: Read> UtmpEntries<R> {
+    /// Creates a new `UtmpEntries` instance reading records from reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> Iterator for UtmpEntries<R> {
+    type Item = Result<UtmpEntry, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = [0; UTMP_SIZE];
+        let mut filled = 0;
+
+        while filled < UTMP_SIZE {
+            match self.reader.read(&mut record[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                // A record truncated by a crash while writing it
+                Ok(0) => return Some(Err(io::ErrorKind::UnexpectedEof.into())),
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        Some(UtmpEntry::from_bytes(&record).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "utmp record time out of range")
+        }))
+    }
+}
+
+/// Returns the records of the users logged in according to the `utmp(5)` file
+/// at path, like `who(1)`.
+pub fn who<P: AsRef<Path>>(path: P) -> Result<Vec<UtmpEntry>, io::Error> {
+    let mut entries = Vec::new();
+
+    for entry in UtmpEntries::open(path)? {
+        let entry = entry?;
+        if entry.is_user_login() {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Returns the sorted login names of the users logged in according to the
+/// `utmp(5)` file at path, once per session, like `users(1)`.
+pub fn users<P: AsRef<Path>>(path: P) -> Result<Vec<OsString>, io::Error> {
+    let mut users: Vec<OsString> = who(path)?.into_iter().map(|entry| entry.user).collect();
+    users.sort_unstable();
+
+    Ok(users)
+}
+
+/// How a login session listed by [`last`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The user logged out at the given time.
+    LoggedOut(SystemTime),
+
+    /// The system was shut down at the given time before the user logged out.
+    Down(SystemTime),
+
+    /// The system booted again at the given time without a recorded shutdown
+    /// since the user logged in, such as after a crash or power loss.
+    Crash(SystemTime),
+
+    /// The user is still logged in.
+    StillLoggedIn,
+}
+
+/// A login session found by [`last`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginSession {
+    login: UtmpEntry,
+    end: SessionEnd,
+}
+
+impl LoginSession {
+    /// Returns the record made at login.
+    #[inline]
+    pub fn login(&self) -> &UtmpEntry {
+        &self.login
+    }
+
+    /// Returns how the session ended.
+    #[inline]
+    pub fn end(&self) -> SessionEnd {
+        self.end
+    }
+}
+
+/// Returns the login sessions recorded in the `wtmp(5)` file at path, most
+/// recent first, like `last(1)`.
+///
+/// Logins are paired with the next logout on the same terminal. Sessions
+/// without a logout before the next shutdown record, a run level record of
+/// user `shutdown`, are reported as [`SessionEnd::Down`], and those without
+/// one before the next boot as [`SessionEnd::Crash`].
+pub fn last<P: AsRef<Path>>(path: P) -> Result<Vec<LoginSession>, io::Error> {
+    let entries = UtmpEntries::open(path)?.collect::<Result<Vec<_>, _>>()?;
+    let mut sessions = Vec::new();
+    let mut logouts: HashMap<OsString, SystemTime> = HashMap::new();
+    let mut next_down = None;
+
+    // Walk back in time, so that every login is seen after its logout
+    for entry in entries.into_iter().rev() {
+        match entry.kind {
+            UtmpType::BootTime => {
+                logouts.clear();
+                next_down = Some(SessionEnd::Crash(entry.time));
+            }
+            UtmpType::RunLevel if entry.user == "shutdown" => {
+                logouts.clear();
+                next_down = Some(SessionEnd::Down(entry.time));
+            }
+            UtmpType::UserProcess if entry.is_user_login() => {
+                let end = match logouts.remove(&entry.line) {
+                    Some(time) => SessionEnd::LoggedOut(time),
+                    None => next_down.unwrap_or(SessionEnd::StillLoggedIn),
+                };
+                sessions.push(LoginSession { login: entry, end });
+            }
+            // Some programs log out by writing a user process record without user
+            UtmpType::DeadProcess | UtmpType::UserProcess if !entry.line.is_empty() => {
+                logouts.insert(entry.line.clone(), entry.time);
+            }
+            _ => (),
+        }
+    }
+
+    Ok(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::os::unix::test_util::TempDir;
+
+    fn record(ut_type: i16, line: &str, user: &str, time: u32) -> Vec<u8> {
+        let mut record = vec![0; UTMP_SIZE];
+        record[UT_TYPE..UT_TYPE + 2].copy_from_slice(&ut_type.to_ne_bytes());
+        record[UT_PID..UT_PID + 4].copy_from_slice(&42i32.to_ne_bytes());
+        record[UT_LINE.0..UT_LINE.0 + line.len()].copy_from_slice(line.as_bytes());
+        record[UT_USER.0..UT_USER.0 + user.len()].copy_from_slice(user.as_bytes());
+        record[UT_HOST.0..UT_HOST.0 + 9].copy_from_slice(b"localhost");
+        let time = if WIDE_UTMP {
+            (time as u64).to_ne_bytes().to_vec()
+        } else {
+            time.to_ne_bytes().to_vec()
+        };
+        record[UT_TV..UT_TV + UT_WORD].copy_from_slice(&time);
+        record[UT_ADDR_V6..UT_ADDR_V6 + 4].copy_from_slice(&[192, 0, 2, 1]);
+        record
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let entry = UtmpEntry::from_bytes(&record(7, "pts/0", "root", 1000)).unwrap();
+
+        assert_eq!(entry.kind(), UtmpType::UserProcess);
+        assert_eq!(entry.pid(), 42);
+        assert_eq!(entry.line(), "pts/0");
+        assert_eq!(entry.user(), "root");
+        assert_eq!(entry.host(), "localhost");
+        assert_eq!(entry.time(), at(1000));
+        assert_eq!(entry.addr(), Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+        assert_eq!(entry.lookup_passwd().unwrap().name(), "root");
+        assert!(UtmpEntry::from_bytes(&[0; 10]).is_none());
+
+        let mut corrupt = record(7, "pts/0", "root", 1000);
+        corrupt[UT_TV..UT_TV + UT_WORD].fill(0xff);
+        let entries: Vec<_> = UtmpEntries::new(&corrupt[..]).collect();
+
+        // tv_sec is u64::MAX with 64-bit words, which no SystemTime can hold
+        assert_eq!(entries.len(), 1);
+        if WIDE_UTMP {
+            assert_eq!(
+                entries[0].as_ref().unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        } else {
+            assert_eq!(entries[0].as_ref().unwrap().time(), at(u32::MAX as u64));
+        }
+    }
+
+    #[test]
+    fn test_queries() {
+        let dir = TempDir::new("wtmp");
+        let path = dir.join("wtmp");
+        let records = [
+            record(2, "~", "reboot", 100),
+            record(7, "tty1", "alice", 110),
+            record(7, "pts/0", "bob", 120),
+            record(8, "pts/0", "", 130),
+            record(2, "~", "reboot", 200),
+            record(7, "tty2", "carol", 205),
+            record(1, "~", "shutdown", 208),
+            // Logouts recorded after a shutdown do not end the sessions before it
+            record(8, "tty2", "", 209),
+            record(2, "~", "reboot", 210),
+            record(7, "pts/1", "bob", 211),
+            record(7, "pts/2", "alice", 220),
+            record(8, "pts/2", "", 230),
+        ];
+        fs::write(&path, records.concat()).unwrap();
+
+        let sessions = last(&path).unwrap();
+        let ends: Vec<SessionEnd> = sessions.iter().map(LoginSession::end).collect();
+
+        assert_eq!(sessions.len(), 5);
+        assert_eq!(sessions[0].login().line(), "pts/2");
+        assert_eq!(
+            ends,
+            [
+                SessionEnd::LoggedOut(at(230)),
+                SessionEnd::StillLoggedIn,
+                SessionEnd::Down(at(208)),
+                SessionEnd::LoggedOut(at(130)),
+                SessionEnd::Crash(at(200)),
+            ]
+        );
+
+        // Taken as a utmp file, every user process record is a current login
+        assert_eq!(who(&path).unwrap().len(), 5);
+        assert_eq!(
+            users(&path).unwrap(),
+            ["alice", "alice", "bob", "bob", "carol"]
+        );
+
+        fs::write(&path, &records.concat()[..UTMP_SIZE + 10]).unwrap();
+        let entries: Vec<_> = UtmpEntries::open(&path).unwrap().collect();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[1].is_err());
+    }
+}
@@ -114,3 +114,12 @@ impl ops::Deref for UseridBuf {
         unsafe { &*(self.0.deref() as *const os_impl::Userid as *const Userid) }
     }
 }
+
+impl private::Sealed for UseridBuf {}
+
+#[cfg(unix)]
+impl crate::os::unix::UseridBufExt for UseridBuf {
+    fn from_raw_uid(uid: libc::uid_t) -> Self {
+        UseridBuf(os_impl::UseridBuf::from_raw_uid(uid))
+    }
+}